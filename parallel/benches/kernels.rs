@@ -0,0 +1,138 @@
+// Scaling report for the element-wise kernels.
+//
+// Every kernel is timed for sizes 1e1..1e8 and for thread counts 1, 2, 4, ... up to the
+// number of available cores, next to a plain sequential loop doing the same work.
+// The report is CSV so that runs from two commits can be diffed directly.
+//
+//     cargo bench -p parallel --bench kernels
+//
+// PARALLEL_BENCH_MAX_SIZE limits the largest size (vc_* kernels at 1e8 need 1.6GB).
+// PARALLEL_BENCH_REPORT writes the report to a file instead of stdout.
+
+use std::hint::black_box;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+type Element = f64;
+
+struct Kernel {
+    name: &'static str,
+    // Bytes read and written per element, used to compute the achieved bandwidth.
+    bytes: usize,
+    parallel: fn(&mut Vec<Element>, &Vec<Element>),
+    sequential: fn(&mut Vec<Element>, &Vec<Element>),
+}
+
+const S: Element = 1.000_001;
+
+fn kernels() -> Vec<Kernel> {
+    let e = std::mem::size_of::<Element>();
+    vec![
+        Kernel{ name: "equal", bytes: 2 * e,
+            parallel: |a, b| { black_box(parallel::equal(a, b)); },
+            sequential: |a, b| { black_box(a.iter().zip(b).all(|(a, b)| *a == *b)); } },
+        Kernel{ name: "default", bytes: e,
+            parallel: |a, _| parallel::default(a),
+            sequential: |a, _| a.iter_mut().for_each(|e| *e = Default::default()) },
+        Kernel{ name: "set", bytes: e,
+            parallel: |a, _| parallel::set(a, S),
+            sequential: |a, _| a.iter_mut().for_each(|e| *e = S) },
+        Kernel{ name: "sc_add", bytes: 2 * e,
+            parallel: |a, _| parallel::sc_add(a, S),
+            sequential: |a, _| a.iter_mut().for_each(|e| *e += S) },
+        Kernel{ name: "sc_sub", bytes: 2 * e,
+            parallel: |a, _| parallel::sc_sub(a, S),
+            sequential: |a, _| a.iter_mut().for_each(|e| *e -= S) },
+        Kernel{ name: "sc_mul", bytes: 2 * e,
+            parallel: |a, _| parallel::sc_mul(a, S),
+            sequential: |a, _| a.iter_mut().for_each(|e| *e *= S) },
+        Kernel{ name: "sc_div", bytes: 2 * e,
+            parallel: |a, _| parallel::sc_div(a, S),
+            sequential: |a, _| a.iter_mut().for_each(|e| *e /= S) },
+        Kernel{ name: "vc_add", bytes: 3 * e,
+            parallel: |a, b| parallel::vc_add(a, b).unwrap(),
+            sequential: |a, b| a.iter_mut().zip(b).for_each(|(a, b)| *a += *b) },
+        Kernel{ name: "vc_sub", bytes: 3 * e,
+            parallel: |a, b| parallel::vc_sub(a, b).unwrap(),
+            sequential: |a, b| a.iter_mut().zip(b).for_each(|(a, b)| *a -= *b) },
+        Kernel{ name: "vc_mul", bytes: 3 * e,
+            parallel: |a, b| parallel::vc_mul(a, b).unwrap(),
+            sequential: |a, b| a.iter_mut().zip(b).for_each(|(a, b)| *a *= *b) },
+        Kernel{ name: "vc_div", bytes: 3 * e,
+            parallel: |a, b| parallel::vc_div(a, b).unwrap(),
+            sequential: |a, b| a.iter_mut().zip(b).for_each(|(a, b)| *a /= *b) },
+    ]
+}
+
+// Best time per call out of a few rounds. Each round repeats the call often enough
+// that small sizes are not dominated by timer resolution.
+fn measure<F: FnMut()>(size: usize, mut f: F) -> Duration {
+    let repeats = (10_000_000 / size).clamp(1, 10_000) as u32;
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..repeats {
+                f();
+            }
+            start.elapsed() / repeats
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let max_size: usize = std::env::var("PARALLEL_BENCH_MAX_SIZE")
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .map(|s| s as usize)
+        .unwrap_or(100_000_000);
+
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let threads: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|n| *n < cores)
+        .chain(std::iter::once(cores))
+        .collect();
+
+    let mut report: Box<dyn Write> = match std::env::var("PARALLEL_BENCH_REPORT") {
+        Ok(path) => Box::new(std::fs::File::create(path).expect("failed to create report")),
+        Err(_) => Box::new(std::io::stdout()),
+    };
+
+    writeln!(report, "kernel,element,size,threads,parallel_ns,sequential_ns,speedup,bandwidth_gbps").unwrap();
+
+    for exponent in 1..=8 {
+        let size = 10usize.pow(exponent);
+        if size > max_size {
+            break;
+        }
+
+        let mut a = vec![1.0 as Element; size];
+        let b = vec![1.0 as Element; size];
+
+        for kernel in kernels() {
+            a.copy_from_slice(&b);
+            let sequential = measure(size, || (kernel.sequential)(black_box(&mut a), black_box(&b)));
+
+            for n in &threads {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(*n)
+                    .build()
+                    .expect("failed to build thread pool");
+                let parallel = pool.install(|| {
+                    measure(size, || (kernel.parallel)(black_box(&mut a), black_box(&b)))
+                });
+
+                let bandwidth = (kernel.bytes * size) as f64 / parallel.as_secs_f64() / 1e9;
+                writeln!(report, "{},{},{},{},{},{},{:.3},{:.3}",
+                    kernel.name,
+                    std::any::type_name::<Element>(),
+                    size,
+                    n,
+                    parallel.as_nanos(),
+                    sequential.as_nanos(),
+                    sequential.as_secs_f64() / parallel.as_secs_f64(),
+                    bandwidth).unwrap();
+            }
+        }
+    }
+}
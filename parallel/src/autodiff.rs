@@ -0,0 +1,450 @@
+use crate::FloatVector;
+use num_traits::{Float, Num, NumCast, One, ToPrimitive, Zero};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::num::FpCategory;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, Sub, SubAssign};
+use std::vec::Vec;
+
+// Forward-mode automatic differentiation.
+//
+// `DualN<T, N>` carries a value together with its partial derivatives with respect to
+// N independent variables. Because it implements `Float` it satisfies `FloatVector`,
+// so any function written against the crate's kernels can be evaluated on duals to get
+// derivatives alongside the result.
+//
+// Comparisons only look at the value, matching how branches in the original function
+// would be taken.
+#[derive(Clone, Copy, Debug)]
+pub struct DualN<T, const N: usize> {
+    pub re: T,
+    pub eps: [T; N],
+}
+
+// A single directional derivative.
+pub type Dual<T> = DualN<T, 1>;
+
+impl<T: Float, const N: usize> DualN<T, N> {
+    pub fn constant(re: T) -> Self {
+        DualN{ re, eps: [T::zero(); N] }
+    }
+
+    // The `index`-th independent variable.
+    pub fn variable(re: T, index: usize) -> Self {
+        let mut eps = [T::zero(); N];
+        eps[index] = T::one();
+        DualN{ re, eps }
+    }
+
+    // Applies a function with value `re` and derivative `derivative` at `self.re`.
+    // Components that don't vary stay zero, even where the derivative is infinite
+    // (e.g. `sqrt` at 0), instead of becoming 0 * inf = NaN.
+    fn chain(self, re: T, derivative: T) -> Self {
+        let mut eps = self.eps;
+        for e in eps.iter_mut() {
+            if *e != T::zero() {
+                *e = *e * derivative;
+            }
+        }
+        DualN{ re, eps }
+    }
+}
+
+impl<T: Float> Dual<T> {
+    pub fn new(re: T, eps: T) -> Self {
+        DualN{ re, eps: [eps] }
+    }
+}
+
+impl<T: Float, const N: usize> Default for DualN<T, N> {
+    fn default() -> Self {
+        Self::constant(T::zero())
+    }
+}
+
+impl<T: Float, const N: usize> PartialEq for DualN<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.re == other.re
+    }
+}
+
+impl<T: Float, const N: usize> PartialOrd for DualN<T, N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.re.partial_cmp(&other.re)
+    }
+}
+
+impl<T: Float, const N: usize> Neg for DualN<T, N> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        self.chain(-self.re, -T::one())
+    }
+}
+
+impl<T: Float, const N: usize> Add for DualN<T, N> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let mut eps = self.eps;
+        for (e, r) in eps.iter_mut().zip(&rhs.eps) {
+            *e = *e + *r;
+        }
+        DualN{ re: self.re + rhs.re, eps }
+    }
+}
+
+impl<T: Float, const N: usize> Sub for DualN<T, N> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        let mut eps = self.eps;
+        for (e, r) in eps.iter_mut().zip(&rhs.eps) {
+            *e = *e - *r;
+        }
+        DualN{ re: self.re - rhs.re, eps }
+    }
+}
+
+impl<T: Float, const N: usize> Mul for DualN<T, N> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        let mut eps = self.eps;
+        for (e, r) in eps.iter_mut().zip(&rhs.eps) {
+            *e = *e * rhs.re + self.re * *r;
+        }
+        DualN{ re: self.re * rhs.re, eps }
+    }
+}
+
+impl<T: Float, const N: usize> Div for DualN<T, N> {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let mut eps = self.eps;
+        for (e, r) in eps.iter_mut().zip(&rhs.eps) {
+            *e = (*e * rhs.re - self.re * *r) / (rhs.re * rhs.re);
+        }
+        DualN{ re: self.re / rhs.re, eps }
+    }
+}
+
+impl<T: Float, const N: usize> Rem for DualN<T, N> {
+    type Output = Self;
+    // a % b = a - b * trunc(a / b)
+    fn rem(self, rhs: Self) -> Self {
+        let q = (self.re / rhs.re).trunc();
+        let mut eps = self.eps;
+        for (e, r) in eps.iter_mut().zip(&rhs.eps) {
+            *e = *e - q * *r;
+        }
+        DualN{ re: self.re % rhs.re, eps }
+    }
+}
+
+impl<T: Float, const N: usize> AddAssign for DualN<T, N> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs
+    }
+}
+
+impl<T: Float, const N: usize> SubAssign for DualN<T, N> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs
+    }
+}
+
+impl<T: Float, const N: usize> MulAssign for DualN<T, N> {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs
+    }
+}
+
+impl<T: Float, const N: usize> DivAssign for DualN<T, N> {
+    fn div_assign(&mut self, rhs: Self) {
+        *self = *self / rhs
+    }
+}
+
+impl<T: Float, const N: usize> Zero for DualN<T, N> {
+    fn zero() -> Self {
+        Self::constant(T::zero())
+    }
+
+    fn is_zero(&self) -> bool {
+        self.re.is_zero()
+    }
+}
+
+impl<T: Float, const N: usize> One for DualN<T, N> {
+    fn one() -> Self {
+        Self::constant(T::one())
+    }
+}
+
+impl<T: Float, const N: usize> Num for DualN<T, N> {
+    type FromStrRadixErr = T::FromStrRadixErr;
+
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        T::from_str_radix(s, radix).map(Self::constant)
+    }
+}
+
+impl<T: Float, const N: usize> ToPrimitive for DualN<T, N> {
+    fn to_i64(&self) -> Option<i64> {
+        self.re.to_i64()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.re.to_u64()
+    }
+
+    fn to_f32(&self) -> Option<f32> {
+        self.re.to_f32()
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        self.re.to_f64()
+    }
+}
+
+impl<T: Float, const N: usize> NumCast for DualN<T, N> {
+    fn from<P: ToPrimitive>(n: P) -> Option<Self> {
+        <T as NumCast>::from(n).map(Self::constant)
+    }
+}
+
+fn two<T: Float>() -> T {
+    T::one() + T::one()
+}
+
+impl<T: Float, const N: usize> Float for DualN<T, N> {
+    fn nan() -> Self { Self::constant(T::nan()) }
+    fn infinity() -> Self { Self::constant(T::infinity()) }
+    fn neg_infinity() -> Self { Self::constant(T::neg_infinity()) }
+    fn neg_zero() -> Self { Self::constant(T::neg_zero()) }
+    fn min_value() -> Self { Self::constant(T::min_value()) }
+    fn min_positive_value() -> Self { Self::constant(T::min_positive_value()) }
+    fn max_value() -> Self { Self::constant(T::max_value()) }
+    fn epsilon() -> Self { Self::constant(T::epsilon()) }
+
+    fn is_nan(self) -> bool { self.re.is_nan() }
+    fn is_infinite(self) -> bool { self.re.is_infinite() }
+    fn is_finite(self) -> bool { self.re.is_finite() }
+    fn is_normal(self) -> bool { self.re.is_normal() }
+    fn classify(self) -> FpCategory { self.re.classify() }
+    fn is_sign_positive(self) -> bool { self.re.is_sign_positive() }
+    fn is_sign_negative(self) -> bool { self.re.is_sign_negative() }
+    fn integer_decode(self) -> (u64, i16, i8) { self.re.integer_decode() }
+
+    // Piecewise constant functions have a zero derivative almost everywhere.
+    fn floor(self) -> Self { Self::constant(self.re.floor()) }
+    fn ceil(self) -> Self { Self::constant(self.re.ceil()) }
+    fn round(self) -> Self { Self::constant(self.re.round()) }
+    fn trunc(self) -> Self { Self::constant(self.re.trunc()) }
+    fn signum(self) -> Self { Self::constant(self.re.signum()) }
+    fn fract(self) -> Self { self.chain(self.re.fract(), T::one()) }
+    fn abs(self) -> Self { self.chain(self.re.abs(), self.re.signum()) }
+
+    fn mul_add(self, a: Self, b: Self) -> Self { self * a + b }
+    fn recip(self) -> Self { self.chain(self.re.recip(), -(self.re * self.re).recip()) }
+
+    fn powi(self, n: i32) -> Self {
+        let n_t = T::from(n).unwrap();
+        self.chain(self.re.powi(n), n_t * self.re.powi(n - 1))
+    }
+
+    // d(a^b) = b a^(b-1) da + a^b ln(a) db. The second term is only added where `b`
+    // actually varies, so that constant exponents work for negative bases.
+    fn powf(self, n: Self) -> Self {
+        let re = self.re.powf(n.re);
+        let da = n.re * self.re.powf(n.re - T::one());
+        let mut eps = self.eps;
+        for (e, dn) in eps.iter_mut().zip(&n.eps) {
+            *e = *e * da;
+            if !dn.is_zero() {
+                *e = *e + re * self.re.ln() * *dn;
+            }
+        }
+        DualN{ re, eps }
+    }
+
+    fn sqrt(self) -> Self {
+        let re = self.re.sqrt();
+        self.chain(re, (two::<T>() * re).recip())
+    }
+
+    fn cbrt(self) -> Self {
+        let re = self.re.cbrt();
+        self.chain(re, (T::from(3).unwrap() * re * re).recip())
+    }
+
+    fn exp(self) -> Self {
+        let re = self.re.exp();
+        self.chain(re, re)
+    }
+
+    fn exp2(self) -> Self {
+        let re = self.re.exp2();
+        self.chain(re, re * two::<T>().ln())
+    }
+
+    fn exp_m1(self) -> Self { self.chain(self.re.exp_m1(), self.re.exp()) }
+    fn ln(self) -> Self { self.chain(self.re.ln(), self.re.recip()) }
+    fn ln_1p(self) -> Self { self.chain(self.re.ln_1p(), (T::one() + self.re).recip()) }
+    fn log(self, base: Self) -> Self { self.ln() / base.ln() }
+    fn log2(self) -> Self { self.chain(self.re.log2(), (self.re * two::<T>().ln()).recip()) }
+    fn log10(self) -> Self { self.chain(self.re.log10(), (self.re * T::from(10).unwrap().ln()).recip()) }
+
+    fn max(self, other: Self) -> Self { if self.re >= other.re || other.re.is_nan() { self } else { other } }
+    fn min(self, other: Self) -> Self { if self.re <= other.re || other.re.is_nan() { self } else { other } }
+
+    #[allow(deprecated)]
+    fn abs_sub(self, other: Self) -> Self {
+        if self.re > other.re { self - other } else { Self::zero() }
+    }
+
+    fn hypot(self, other: Self) -> Self { (self * self + other * other).sqrt() }
+
+    fn sin(self) -> Self { self.chain(self.re.sin(), self.re.cos()) }
+    fn cos(self) -> Self { self.chain(self.re.cos(), -self.re.sin()) }
+
+    fn tan(self) -> Self {
+        let re = self.re.tan();
+        self.chain(re, T::one() + re * re)
+    }
+
+    fn sin_cos(self) -> (Self, Self) { (self.sin(), self.cos()) }
+    fn asin(self) -> Self { self.chain(self.re.asin(), (T::one() - self.re * self.re).sqrt().recip()) }
+    fn acos(self) -> Self { self.chain(self.re.acos(), -(T::one() - self.re * self.re).sqrt().recip()) }
+    fn atan(self) -> Self { self.chain(self.re.atan(), (T::one() + self.re * self.re).recip()) }
+
+    // atan2(y, x) with y = self.
+    fn atan2(self, other: Self) -> Self {
+        let d = self.re * self.re + other.re * other.re;
+        let mut eps = self.eps;
+        for (e, o) in eps.iter_mut().zip(&other.eps) {
+            *e = (other.re * *e - self.re * *o) / d;
+        }
+        DualN{ re: self.re.atan2(other.re), eps }
+    }
+
+    fn sinh(self) -> Self { self.chain(self.re.sinh(), self.re.cosh()) }
+    fn cosh(self) -> Self { self.chain(self.re.cosh(), self.re.sinh()) }
+
+    fn tanh(self) -> Self {
+        let re = self.re.tanh();
+        self.chain(re, T::one() - re * re)
+    }
+
+    fn asinh(self) -> Self { self.chain(self.re.asinh(), (self.re * self.re + T::one()).sqrt().recip()) }
+    fn acosh(self) -> Self { self.chain(self.re.acosh(), (self.re * self.re - T::one()).sqrt().recip()) }
+    fn atanh(self) -> Self { self.chain(self.re.atanh(), (T::one() - self.re * self.re).recip()) }
+}
+
+// The value and derivative of a scalar function at `x`.
+pub fn derivative<T, F>(f: F, x: T) -> (T, T) where
+    T: FloatVector,
+    F: Fn(Dual<T>) -> Dual<T>,
+{
+    let y = f(Dual::variable(x, 0));
+    (y.re, y.eps[0])
+}
+
+// The gradient of `f` at `x`, with one forward pass per component run in parallel.
+pub fn gradient<T, F>(f: F, x: &[T]) -> Vec<T> where
+    T: FloatVector,
+    F: Fn(&Vec<Dual<T>>) -> Dual<T> + Sync,
+{
+    (0..x.len()).into_par_iter()
+        .map(|i| {
+            let seeded: Vec<Dual<T>> = x.iter()
+                .enumerate()
+                .map(|(j, e)| Dual::new(*e, if i == j { T::one() } else { T::zero() }))
+                .collect();
+            f(&seeded).eps[0]
+        })
+        .collect()
+}
+
+// The value and gradient of `f` at `x` in a single forward pass using `N` components.
+pub fn gradient_n<T, F, const N: usize>(f: F, x: &[T; N]) -> (T, [T; N]) where
+    T: FloatVector,
+    F: Fn(&Vec<DualN<T, N>>) -> DualN<T, N>,
+{
+    let seeded: Vec<DualN<T, N>> = x.iter()
+        .enumerate()
+        .map(|(i, e)| DualN::variable(*e, i))
+        .collect();
+    let y = f(&seeded);
+    (y.re, y.eps)
+}
+
+// f(x) and the Jacobian-vector product J(x) v, for a function that transforms its
+// argument in place (e.g. a sequence of `sc_*`/`vc_*` kernel calls).
+pub fn jvp<T, F>(f: F, x: &[T], v: &[T]) -> (Vec<T>, Vec<T>) where
+    T: FloatVector,
+    F: Fn(&mut Vec<Dual<T>>),
+{
+    let mut seeded: Vec<Dual<T>> = x.par_iter().zip(v).map(|(x, v)| Dual::new(*x, *v)).collect();
+    f(&mut seeded);
+    seeded.par_iter().map(|e| (e.re, e.eps[0])).unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalar_derivatives() {
+        let (y, dy) = derivative(|x| x * x.sin() + x.exp() / x, 2.0f64);
+        assert!((y - (2.0 * 2.0f64.sin() + 2.0f64.exp() / 2.0)).abs() < 1e-12);
+        assert!((dy - (2.0f64.sin() + 2.0 * 2.0f64.cos() + 2.0f64.exp() / 4.0)).abs() < 1e-12);
+
+        let (_, dy) = derivative(|x| x.powf(Dual::constant(3.0)), -2.0f64);
+        assert_eq!(dy, 12.0);
+
+        let (_, dy) = derivative(|x| Dual::constant(2.0).powf(x), 3.0f64);
+        assert!((dy - 8.0 * 2.0f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn kernels_on_duals() {
+        // f(x) = 3 * x * x + 1, element-wise, written against the crate's kernels.
+        let f = |v: &mut Vec<Dual<f64>>| {
+            let copy = v.clone();
+            crate::vc_mul(v, &copy).unwrap();
+            crate::sc_mul(v, Dual::constant(3.0));
+            crate::sc_add(v, Dual::constant(1.0));
+        };
+
+        let (y, jv) = jvp(f, &[1.0, 2.0, 3.0], &[1.0, 1.0, 0.5]);
+        assert_eq!(y, vec![4.0, 13.0, 28.0]);
+        assert_eq!(jv, vec![6.0, 12.0, 9.0]);
+
+        // g(x) = sum(x_i^2) * x_0
+        let g = |v: &Vec<DualN<f64, 3>>| {
+            let mut sq = v.clone();
+            crate::vc_mul(&mut sq, v).unwrap();
+            sq.iter().fold(DualN::zero(), |acc, e| acc + *e) * v[0]
+        };
+        let (y, grad) = gradient_n(g, &[1.0, 2.0, 3.0]);
+        assert_eq!(y, 14.0);
+        assert_eq!(grad, [16.0, 4.0, 6.0]);
+
+        let h = |v: &Vec<Dual<f64>>| v[0] * v[1].ln();
+        assert_eq!(gradient(h, &[2.0, 1.0]), vec![0.0, 2.0]);
+    }
+
+    #[test]
+    fn singular_points() {
+        // Constants have no tangent, even where the derivative is infinite.
+        let zero = Dual::constant(0.0f64);
+        for y in [zero.sqrt(), zero.ln(), zero.hypot(zero), zero.powi(0), zero.cbrt()] {
+            assert_eq!(y.eps, [0.0]);
+        }
+
+        // Directions a value doesn't depend on are left alone.
+        let x = DualN::<f64, 2>::variable(2.0, 0);
+        let y = DualN::<f64, 2>::variable(0.0, 1);
+        let z = x + y.sqrt();
+        assert_eq!(z.eps[0], 1.0);
+        assert_eq!(z.eps[1], f64::INFINITY);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::sort::total_cmp;
+use crate::{FloatVector, ShapeError};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+// Distances between rows. Every row of both sets must have the same dimension.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Euclidean,
+    SquaredEuclidean,
+    Manhattan,
+    // 1 - cos(angle). A zero vector is treated as orthogonal to everything.
+    Cosine,
+}
+
+impl Metric {
+    pub fn distance<T: FloatVector>(&self, a: &[T], b: &[T]) -> T {
+        match self {
+            Metric::Euclidean => Metric::SquaredEuclidean.distance(a, b).sqrt(),
+            Metric::SquaredEuclidean => a.iter().zip(b).fold(T::zero(), |acc, (a, b)| {
+                acc + (*a - *b) * (*a - *b)
+            }),
+            Metric::Manhattan => a.iter().zip(b).fold(T::zero(), |acc, (a, b)| {
+                acc + (*a - *b).abs()
+            }),
+            Metric::Cosine => {
+                let (ab, aa, bb) = a.iter().zip(b).fold(
+                    (T::zero(), T::zero(), T::zero()),
+                    |(ab, aa, bb), (a, b)| (ab + *a * *b, aa + *a * *a, bb + *b * *b));
+                if aa == T::zero() || bb == T::zero() {
+                    T::one()
+                } else {
+                    T::one() - ab / (aa.sqrt() * bb.sqrt())
+                }
+            }
+        }
+    }
+}
+
+fn check_rows<T>(a: &[Vec<T>], b: &[Vec<T>]) -> Result<(), ShapeError>
+{
+    let expected = match a.first().or_else(|| b.first()) {
+        Some(row) => row.len(),
+        None => return Ok(()),
+    };
+    match a.iter().chain(b).find(|row| row.len() != expected) {
+        Some(row) => Err(ShapeError{ expected, found: row.len() }),
+        None => Ok(()),
+    }
+}
+
+// The full `a.len()` x `b.len()` distance matrix, computed row by row in parallel.
+pub fn pairwise<T: FloatVector>(a: &[Vec<T>], b: &[Vec<T>], metric: Metric)
+    -> Result<Vec<Vec<T>>, ShapeError>
+{
+    check_rows(a, b)?;
+    Ok(a.par_iter()
+        .map(|a| b.iter().map(|b| metric.distance(a, b)).collect())
+        .collect())
+}
+
+// Keeps the `k` nearest (index, distance) pairs, nearest first.
+// Ties are broken in favour of the lower index.
+fn nearest<T: FloatVector>(mut candidates: Vec<(usize, T)>, k: usize) -> Vec<(usize, T)>
+{
+    let ascending = |a: &(usize, T), b: &(usize, T)| total_cmp(&a.1, &b.1).then(a.0.cmp(&b.0));
+    if candidates.len() > k {
+        candidates.select_nth_unstable_by(k, ascending);
+        candidates.truncate(k);
+    }
+    candidates.sort_unstable_by(ascending);
+    candidates
+}
+
+// Brute force k-nearest-neighbour query: the `k` nearest rows of `points` for every row of
+// `queries`, as (index, distance) pairs, nearest first.
+pub fn knn<T: FloatVector>(queries: &[Vec<T>], points: &[Vec<T>], k: usize, metric: Metric)
+    -> Result<Vec<Vec<(usize, T)>>, ShapeError>
+{
+    Ok(pairwise(queries, points, metric)?
+        .into_par_iter()
+        .map(|row| nearest(row.into_iter().enumerate().collect(), k))
+        .collect())
+}
+
+// Same result as `knn`, but only a `block_size` x `block_size` tile of distances exists
+// at any time per worker, so memory stays bounded for large `points`.
+pub fn knn_blocked<T: FloatVector>(
+    queries: &[Vec<T>],
+    points: &[Vec<T>],
+    k: usize,
+    metric: Metric,
+    block_size: usize,
+) -> Result<Vec<Vec<(usize, T)>>, ShapeError>
+{
+    check_rows(queries, points)?;
+    let block_size = block_size.max(1);
+
+    Ok(queries.par_chunks(block_size)
+        .flat_map_iter(|queries| {
+            let mut best: Vec<Vec<(usize, T)>> = vec![Vec::with_capacity(2 * k); queries.len()];
+            for (offset, points) in points.chunks(block_size).enumerate() {
+                for (query, best) in queries.iter().zip(best.iter_mut()) {
+                    best.extend(points.iter()
+                        .enumerate()
+                        .map(|(i, point)| (offset * block_size + i, metric.distance(query, point))));
+                    *best = nearest(std::mem::take(best), k);
+                }
+            }
+            best
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        let a = vec![vec![0.0, 0.0], vec![1.0, 1.0]];
+        let b = vec![vec![3.0, 4.0], vec![-1.0, 1.0]];
+
+        assert_eq!(pairwise(&a, &b, Metric::Euclidean).unwrap(), vec![vec![5.0, 2.0f64.sqrt()], vec![13.0f64.sqrt(), 2.0]]);
+        assert_eq!(pairwise(&a, &b, Metric::SquaredEuclidean).unwrap(), vec![vec![25.0, 2.0], vec![13.0, 4.0]]);
+        assert_eq!(pairwise(&a, &b, Metric::Manhattan).unwrap(), vec![vec![7.0, 2.0], vec![5.0, 2.0]]);
+
+        let cosine = pairwise(&a, &b, Metric::Cosine).unwrap();
+        assert_eq!(cosine[0], vec![1.0, 1.0]);
+        assert!((cosine[1][1] - 1.0).abs() < 1e-12);
+
+        let c = vec![vec![1.0, 2.0, 3.0]];
+        assert_eq!(pairwise(&a, &c, Metric::Euclidean), Err(ShapeError{ expected: 2, found: 3 }));
+    }
+
+    #[test]
+    fn nearest_neighbours() {
+        let points: Vec<Vec<f32>> = (0..100).map(|i| vec![(i % 10) as f32, (i / 10) as f32]).collect();
+        let queries = vec![vec![0.1, 0.1], vec![5.0, 5.2], vec![9.0, 9.0]];
+
+        let expected = knn(&queries, &points, 3, Metric::Euclidean).unwrap();
+        assert_eq!(expected[0].iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![0, 1, 10]);
+        assert_eq!(expected[1][0].0, 55);
+        assert_eq!(expected[2][0], (99, 0.0));
+
+        for block_size in &[1, 7, 64, 1000] {
+            assert_eq!(knn_blocked(&queries, &points, 3, Metric::Euclidean, *block_size).unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{FloatVector, ShapeError};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+// Linear least squares: find `x` minimising |A x - y|^2 (optionally weighted), where the
+// design matrix A is given as rows.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    // Solve (A^T A) x = A^T y with a Cholesky factorisation. Fast, but squares the
+    // condition number of A.
+    NormalEquations,
+    // Householder QR factorisation of A. Slower, but numerically stable.
+    Qr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    Shape(ShapeError),
+    // Fewer rows than unknowns.
+    Underdetermined { rows: usize, columns: usize },
+    // The columns of A are (numerically) linearly dependent.
+    Singular,
+}
+
+impl From<ShapeError> for Error {
+    fn from(e: ShapeError) -> Self {
+        Error::Shape(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Fit<T> {
+    pub coefficients: Vec<T>,
+    // y - A x, unweighted.
+    pub residuals: Vec<T>,
+    // Weighted residual sum of squares.
+    pub residual_sum_of_squares: T,
+    // Coefficient of determination, 1 - SS_res / SS_tot.
+    pub r_squared: T,
+}
+
+fn check<T>(a: &[Vec<T>], y: &[T], weights: Option<&[T]>) -> Result<usize, Error>
+{
+    if a.len() != y.len() {
+        return Err(ShapeError{ expected: a.len(), found: y.len() }.into());
+    }
+    if let Some(w) = weights {
+        if w.len() != y.len() {
+            return Err(ShapeError{ expected: y.len(), found: w.len() }.into());
+        }
+    }
+    let columns = a.first().map(|row| row.len()).unwrap_or(0);
+    if let Some(row) = a.iter().find(|row| row.len() != columns) {
+        return Err(ShapeError{ expected: columns, found: row.len() }.into());
+    }
+    if a.len() < columns || columns == 0 {
+        return Err(Error::Underdetermined{ rows: a.len(), columns });
+    }
+    Ok(columns)
+}
+
+// A^T A and A^T y, accumulated over rows in parallel.
+fn normal_equations<T: FloatVector>(a: &[Vec<T>], y: &[T], columns: usize) -> (Vec<Vec<T>>, Vec<T>)
+{
+    a.par_iter()
+        .zip(y)
+        .fold(
+            || (vec![vec![T::zero(); columns]; columns], vec![T::zero(); columns]),
+            |(mut ata, mut aty), (row, y)| {
+                for i in 0..columns {
+                    for j in 0..=i {
+                        ata[i][j] += row[i] * row[j];
+                    }
+                    aty[i] += row[i] * *y;
+                }
+                (ata, aty)
+            })
+        .reduce(
+            || (vec![vec![T::zero(); columns]; columns], vec![T::zero(); columns]),
+            |(mut ata, mut aty), (b_ata, b_aty)| {
+                for (a, b) in ata.iter_mut().zip(b_ata) {
+                    crate::vc_add(a, &b).unwrap();
+                }
+                crate::vc_add(&mut aty, &b_aty).unwrap();
+                (ata, aty)
+            })
+}
+
+// Solves the symmetric positive definite system whose lower triangle is stored in `m`.
+fn cholesky<T: FloatVector>(mut m: Vec<Vec<T>>, mut b: Vec<T>) -> Result<Vec<T>, Error>
+{
+    let n = b.len();
+    for j in 0..n {
+        let d = m[j][j] - m[j][..j].iter().fold(T::zero(), |acc, e| acc + *e * *e);
+        if d <= T::epsilon() * T::from(n).unwrap() * m[j][j] || !d.is_finite() {
+            return Err(Error::Singular);
+        }
+        let d = d.sqrt();
+        m[j][j] = d;
+        for i in j + 1..n {
+            let s = m[i][..j].iter().zip(&m[j][..j]).fold(T::zero(), |acc, (a, b)| acc + *a * *b);
+            m[i][j] = (m[i][j] - s) / d;
+        }
+    }
+
+    // L z = b, then L^T x = z.
+    for i in 0..n {
+        for k in 0..i {
+            let l = m[i][k] * b[k];
+            b[i] -= l;
+        }
+        b[i] /= m[i][i];
+    }
+    for i in (0..n).rev() {
+        for k in i + 1..n {
+            let l = m[k][i] * b[k];
+            b[i] -= l;
+        }
+        b[i] /= m[i][i];
+    }
+    Ok(b)
+}
+
+// Householder QR on a column-major copy of A. Each reflector is applied to the remaining
+// columns in parallel.
+fn qr<T: FloatVector>(a: &[Vec<T>], y: &[T], columns: usize) -> Result<Vec<T>, Error>
+{
+    let rows = a.len();
+    let mut q: Vec<Vec<T>> = (0..columns).into_par_iter()
+        .map(|j| a.iter().map(|row| row[j]).collect())
+        .collect();
+    let mut qty = y.to_vec();
+    let mut largest = T::zero();
+
+    for k in 0..columns {
+        let (done, rest) = q.split_at_mut(k + 1);
+        let v = &mut done[k];
+
+        let norm = v[k..].iter().fold(T::zero(), |acc, e| acc + *e * *e).sqrt();
+        largest = largest.max(norm);
+        if norm <= T::epsilon() * largest * T::from(rows).unwrap() {
+            return Err(Error::Singular);
+        }
+
+        // v = x - alpha e1, with alpha chosen to avoid cancellation.
+        let alpha = if v[k] > T::zero() { -norm } else { norm };
+        v[k] -= alpha;
+        let vtv = v[k..].iter().fold(T::zero(), |acc, e| acc + *e * *e);
+
+        let reflect = |column: &mut Vec<T>| {
+            let dot = v[k..].iter().zip(&column[k..]).fold(T::zero(), |acc, (v, c)| acc + *v * *c);
+            let scale = (dot + dot) / vtv;
+            for (c, v) in column[k..].iter_mut().zip(&v[k..]) {
+                *c -= scale * *v;
+            }
+        };
+
+        rest.par_iter_mut().for_each(reflect);
+        reflect(&mut qty);
+
+        // The diagonal of R; the rest of the reflector is no longer needed.
+        v[k] = alpha;
+    }
+
+    // R x = Q^T y, where R[i][j] = q[j][i] for i <= j.
+    let mut x = vec![T::zero(); columns];
+    for i in (0..columns).rev() {
+        let mut s = qty[i];
+        for j in i + 1..columns {
+            s -= q[j][i] * x[j];
+        }
+        x[i] = s / q[i][i];
+    }
+    Ok(x)
+}
+
+fn solve<T: FloatVector>(a: &[Vec<T>], y: &[T], weights: Option<&[T]>, method: Method) -> Result<Fit<T>, Error>
+{
+    let columns = check(a, y, weights)?;
+
+    // Weighted least squares is ordinary least squares on rows scaled by sqrt(w).
+    let (scaled_a, scaled_y);
+    let (a_w, y_w): (&[Vec<T>], &[T]) = match weights {
+        Some(w) => {
+            scaled_a = a.par_iter().zip(w).map(|(row, w)| {
+                let mut row = row.clone();
+                crate::sc_mul(&mut row, w.sqrt());
+                row
+            }).collect::<Vec<_>>();
+            scaled_y = y.par_iter().zip(w).map(|(y, w)| *y * w.sqrt()).collect::<Vec<_>>();
+            (&scaled_a, &scaled_y)
+        }
+        None => (a, y),
+    };
+
+    let coefficients = match method {
+        Method::NormalEquations => {
+            let (ata, aty) = normal_equations(a_w, y_w, columns);
+            cholesky(ata, aty)?
+        }
+        Method::Qr => qr(a_w, y_w, columns)?,
+    };
+
+    let residuals: Vec<T> = a.par_iter()
+        .zip(y)
+        .map(|(row, y)| *y - row.iter().zip(&coefficients).fold(T::zero(), |acc, (a, c)| acc + *a * *c))
+        .collect();
+
+    let weight = |i: usize| weights.map(|w| w[i]).unwrap_or_else(T::one);
+    let total_weight = (0..y.len()).into_par_iter().map(weight).reduce(T::zero, |a, b| a + b);
+    let mean = (0..y.len()).into_par_iter().map(|i| weight(i) * y[i]).reduce(T::zero, |a, b| a + b) / total_weight;
+    let ss_res = (0..y.len()).into_par_iter()
+        .map(|i| weight(i) * residuals[i] * residuals[i])
+        .reduce(T::zero, |a, b| a + b);
+    let ss_tot = (0..y.len()).into_par_iter()
+        .map(|i| weight(i) * (y[i] - mean) * (y[i] - mean))
+        .reduce(T::zero, |a, b| a + b);
+
+    Ok(Fit{
+        coefficients,
+        residuals,
+        residual_sum_of_squares: ss_res,
+        r_squared: if ss_tot > T::zero() { T::one() - ss_res / ss_tot } else { T::one() },
+    })
+}
+
+pub fn least_squares<T: FloatVector>(a: &[Vec<T>], y: &[T], method: Method) -> Result<Fit<T>, Error>
+{
+    solve(a, y, None, method)
+}
+
+pub fn weighted_least_squares<T: FloatVector>(a: &[Vec<T>], y: &[T], weights: &[T], method: Method)
+    -> Result<Fit<T>, Error>
+{
+    solve(a, y, Some(weights), method)
+}
+
+// Fits y = c[0] + c[1] x + ... + c[degree] x^degree using QR.
+pub fn polyfit<T: FloatVector>(x: &[T], y: &[T], degree: usize, weights: Option<&[T]>) -> Result<Fit<T>, Error>
+{
+    let vandermonde: Vec<Vec<T>> = x.par_iter()
+        .map(|x| std::iter::successors(Some(T::one()), |p| Some(*p * *x)).take(degree + 1).collect())
+        .collect();
+    solve(&vandermonde, y, weights, Method::Qr)
+}
+
+// Evaluates the polynomial with coefficients `c` (lowest order first) at every `x`.
+pub fn polyval<T: FloatVector>(c: &[T], x: &[T]) -> Vec<T>
+{
+    x.par_iter()
+        .map(|x| c.iter().rev().fold(T::zero(), |acc, c| acc * *x + *c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_regression() {
+        // y = 1 + 2 a + 3 b, exactly.
+        let a: Vec<Vec<f64>> = (0..50).map(|i| vec![1.0, i as f64, ((i * 7) % 11) as f64]).collect();
+        let y: Vec<f64> = a.iter().map(|r| 1.0 + 2.0 * r[1] + 3.0 * r[2]).collect();
+
+        for method in &[Method::NormalEquations, Method::Qr] {
+            let fit = least_squares(&a, &y, *method).unwrap();
+            for (c, e) in fit.coefficients.iter().zip(&[1.0, 2.0, 3.0]) {
+                assert!((c - e).abs() < 1e-9);
+            }
+            assert!((fit.r_squared - 1.0).abs() < 1e-12);
+            assert!(fit.residual_sum_of_squares < 1e-12);
+        }
+
+        let dependent: Vec<Vec<f64>> = a.iter().map(|r| vec![r[1], 2.0 * r[1]]).collect();
+        assert_eq!(least_squares(&dependent, &y, Method::Qr).unwrap_err(), Error::Singular);
+        assert_eq!(least_squares(&dependent, &y, Method::NormalEquations).unwrap_err(), Error::Singular);
+        assert_eq!(least_squares(&a[..2], &y[..2], Method::Qr).unwrap_err(), Error::Underdetermined{ rows: 2, columns: 3 });
+    }
+
+    #[test]
+    fn polynomial() {
+        let x: Vec<f64> = (0..20).map(|i| i as f64 / 4.0).collect();
+        let mut y = polyval(&[0.5, -1.0, 0.25], &x);
+
+        let fit = polyfit(&x, &y, 2, None).unwrap();
+        for (c, e) in fit.coefficients.iter().zip(&[0.5, -1.0, 0.25]) {
+            assert!((c - e).abs() < 1e-9);
+        }
+
+        // An outlier with zero weight has no influence on a weighted fit.
+        y[5] += 100.0;
+        let mut w = vec![1.0; x.len()];
+        w[5] = 0.0;
+        let fit = polyfit(&x, &y, 2, Some(&w)).unwrap();
+        for (c, e) in fit.coefficients.iter().zip(&[0.5, -1.0, 0.25]) {
+            assert!((c - e).abs() < 1e-9);
+        }
+        assert!((fit.residuals[5] - 100.0).abs() < 1e-9);
+        assert!(polyfit(&x, &y, 2, None).unwrap().r_squared < 0.9);
+    }
+}
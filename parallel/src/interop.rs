@@ -0,0 +1,226 @@
+use crate::{FloatVector, ShapeError};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+// Runs the crate's kernels directly on the storage of other container types.
+//
+// The kernels work on slices, so any container that keeps its elements contiguously can
+// be used without copying. `ndarray` arrays and `nalgebra` matrices are supported behind
+// the cargo features of the same name.
+
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Shape(ShapeError),
+    // Same number of elements, but different shapes (e.g. 2x3 and 3x2).
+    Dimensions(Vec<usize>, Vec<usize>),
+    // Same shape, but one container is row-major and the other column-major, so their
+    // elements don't line up in memory.
+    Order(Order, Order),
+    // The container's elements are not stored contiguously in its logical order
+    // (e.g. a strided or transposed view).
+    NotContiguous,
+}
+
+impl From<ShapeError> for Error {
+    fn from(e: ShapeError) -> Self {
+        Error::Shape(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    RowMajor,
+    ColumnMajor,
+}
+
+// The logical shape (rows first for matrices) and the order the elements are stored in.
+// Two containers whose layouts match store corresponding elements at the same offsets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub shape: Vec<usize>,
+    pub order: Order,
+}
+
+impl Layout {
+    // Axes of length one don't affect offsets, so a column vector and a 1-D array match.
+    fn normalised(&self) -> Vec<usize> {
+        self.shape.iter().copied().filter(|n| *n != 1).collect()
+    }
+}
+
+pub trait Contiguous<T> {
+    fn layout(&self) -> Layout;
+    fn contiguous(&self) -> Result<&[T], Error>;
+}
+
+pub trait ContiguousMut<T>: Contiguous<T> {
+    fn contiguous_mut(&mut self) -> Result<&mut [T], Error>;
+}
+
+impl<T> Contiguous<T> for Vec<T> {
+    fn layout(&self) -> Layout { Layout{ shape: vec![self.len()], order: Order::RowMajor } }
+    fn contiguous(&self) -> Result<&[T], Error> { Ok(self) }
+}
+
+impl<T> ContiguousMut<T> for Vec<T> {
+    fn contiguous_mut(&mut self) -> Result<&mut [T], Error> { Ok(self) }
+}
+
+fn check<T, A, B>(a: &A, b: &B) -> Result<(), Error> where
+    A: Contiguous<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    let (a, b) = (a.layout(), b.layout());
+    let (na, nb) = (a.shape.iter().product::<usize>(), b.shape.iter().product::<usize>());
+    if na != nb {
+        return Err(ShapeError{ expected: na, found: nb }.into());
+    }
+    let shape = a.normalised();
+    if shape != b.normalised() {
+        return Err(Error::Dimensions(a.shape, b.shape));
+    }
+    // With at most one axis longer than one, the order doesn't change the offsets.
+    if shape.len() > 1 && a.order != b.order {
+        return Err(Error::Order(a.order, b.order));
+    }
+    Ok(())
+}
+
+pub fn default<T: FloatVector, C: ContiguousMut<T> + ?Sized>(v: &mut C) -> Result<(), Error>
+{
+    crate::default(v.contiguous_mut()?);
+    Ok(())
+}
+
+pub fn set<T: FloatVector, C: ContiguousMut<T> + ?Sized>(v: &mut C, s: T) -> Result<(), Error>
+{
+    crate::set(v.contiguous_mut()?, s);
+    Ok(())
+}
+
+pub fn sc_add<T: FloatVector, C: ContiguousMut<T> + ?Sized>(v: &mut C, s: T) -> Result<(), Error>
+{
+    crate::sc_add(v.contiguous_mut()?, s);
+    Ok(())
+}
+
+pub fn sc_div<T: FloatVector, C: ContiguousMut<T> + ?Sized>(v: &mut C, s: T) -> Result<(), Error>
+{
+    crate::sc_div(v.contiguous_mut()?, s);
+    Ok(())
+}
+
+pub fn sc_mul<T: FloatVector, C: ContiguousMut<T> + ?Sized>(v: &mut C, s: T) -> Result<(), Error>
+{
+    crate::sc_mul(v.contiguous_mut()?, s);
+    Ok(())
+}
+
+pub fn sc_sub<T: FloatVector, C: ContiguousMut<T> + ?Sized>(v: &mut C, s: T) -> Result<(), Error>
+{
+    crate::sc_sub(v.contiguous_mut()?, s);
+    Ok(())
+}
+
+pub fn equal<T, A, B>(a: &A, b: &B) -> Result<bool, Error> where
+    T: FloatVector,
+    A: Contiguous<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    if check(a, b).is_err() {
+        return Ok(false);
+    }
+    Ok(crate::equal(a.contiguous()?, b.contiguous()?))
+}
+
+pub fn vc_add<T, A, B>(a: &mut A, b: &B) -> Result<(), Error> where
+    T: FloatVector,
+    A: ContiguousMut<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    check(a, b)?;
+    Ok(crate::vc_add(a.contiguous_mut()?, b.contiguous()?)?)
+}
+
+pub fn vc_div<T, A, B>(a: &mut A, b: &B) -> Result<(), Error> where
+    T: FloatVector,
+    A: ContiguousMut<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    check(a, b)?;
+    Ok(crate::vc_div(a.contiguous_mut()?, b.contiguous()?)?)
+}
+
+pub fn vc_mul<T, A, B>(a: &mut A, b: &B) -> Result<(), Error> where
+    T: FloatVector,
+    A: ContiguousMut<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    check(a, b)?;
+    Ok(crate::vc_mul(a.contiguous_mut()?, b.contiguous()?)?)
+}
+
+pub fn vc_sub<T, A, B>(a: &mut A, b: &B) -> Result<(), Error> where
+    T: FloatVector,
+    A: ContiguousMut<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    check(a, b)?;
+    Ok(crate::vc_sub(a.contiguous_mut()?, b.contiguous()?)?)
+}
+
+// The reductions accumulate in `T`. For half-precision storage, `crate::precision` on
+// `contiguous()` widens to f32 first.
+
+pub fn sum<T: FloatVector, C: Contiguous<T> + ?Sized>(v: &C) -> Result<T, Error>
+{
+    Ok(v.contiguous()?.par_iter().copied().reduce(T::zero, |a, b| a + b))
+}
+
+pub fn mean<T: FloatVector, C: Contiguous<T> + ?Sized>(v: &C) -> Result<T, Error>
+{
+    let n = v.contiguous()?.len();
+    Ok(sum(v)? / T::from(n).unwrap())
+}
+
+pub fn norm<T: FloatVector, C: Contiguous<T> + ?Sized>(v: &C) -> Result<T, Error>
+{
+    Ok(dot(v, v)?.sqrt())
+}
+
+pub fn dot<T, A, B>(a: &A, b: &B) -> Result<T, Error> where
+    T: FloatVector,
+    A: Contiguous<T> + ?Sized,
+    B: Contiguous<T> + ?Sized,
+{
+    check(a, b)?;
+    Ok(a.contiguous()?.par_iter().zip(b.contiguous()?).map(|(a, b)| *a * *b).reduce(T::zero, |a, b| a + b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors() {
+        let mut a = vec![1.0f32, 2.0, 3.0];
+        let b = vec![1.0f32, 1.0, 1.0];
+
+        vc_add(&mut a, &b).unwrap();
+        sc_mul(&mut a, 2.0).unwrap();
+        assert_eq!(a, vec![4.0, 6.0, 8.0]);
+        assert_eq!(sum(&a), Ok(18.0));
+        assert_eq!(dot(&a, &b), Ok(18.0));
+        assert_eq!(vc_add(&mut a, &vec![1.0]), Err(Error::Shape(ShapeError{ expected: 3, found: 1 })));
+
+        let v = vec![3.0f64, 4.0];
+        assert_eq!(sum(&v), Ok(7.0));
+        assert_eq!(mean(&v), Ok(3.5));
+        assert_eq!(norm(&v), Ok(5.0));
+    }
+}
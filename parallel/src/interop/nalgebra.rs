@@ -0,0 +1,92 @@
+use super::{Contiguous, ContiguousMut, Error, Layout, Order};
+use crate::ShapeError;
+use ::nalgebra::{DMatrix, DVector, Dim, Dyn, Matrix, Scalar, VecStorage};
+use std::vec::Vec;
+
+// Heap-allocated matrices (`DMatrix`, `DVector`) always store their elements
+// contiguously in column-major order.
+
+impl<T: Scalar, C: Dim> Contiguous<T> for Matrix<T, Dyn, C, VecStorage<T, Dyn, C>> {
+    fn layout(&self) -> Layout {
+        Layout{ shape: vec![self.nrows(), self.ncols()], order: Order::ColumnMajor }
+    }
+
+    fn contiguous(&self) -> Result<&[T], Error> {
+        Ok(self.as_slice())
+    }
+}
+
+impl<T: Scalar, C: Dim> ContiguousMut<T> for Matrix<T, Dyn, C, VecStorage<T, Dyn, C>> {
+    fn contiguous_mut(&mut self) -> Result<&mut [T], Error> {
+        Ok(self.as_mut_slice())
+    }
+}
+
+// Zero-copy conversions between `Vec` and dynamically sized matrices.
+
+pub fn from_vec<T: Scalar>(v: Vec<T>) -> DVector<T>
+{
+    DVector::from_vec(v)
+}
+
+// `v` holds the elements in column-major order.
+pub fn from_vec2<T: Scalar>(v: Vec<T>, rows: usize, columns: usize) -> Result<DMatrix<T>, Error>
+{
+    if v.len() != rows * columns {
+        return Err(ShapeError{ expected: rows * columns, found: v.len() }.into());
+    }
+    Ok(DMatrix::from_vec(rows, columns, v))
+}
+
+pub fn into_vec<T: Scalar, R: Dim, C: Dim>(m: Matrix<T, R, C, VecStorage<T, R, C>>) -> Vec<T>
+{
+    m.data.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interop;
+
+    #[test]
+    fn matrices() {
+        let mut a = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let b = from_vec2(vec![1.0; 6], 2, 3).unwrap();
+
+        interop::vc_add(&mut a, &b).unwrap();
+        interop::sc_mul(&mut a, 0.5).unwrap();
+        assert_eq!(a, DMatrix::from_row_slice(2, 3, &[1.0, 1.5, 2.0, 2.5, 3.0, 3.5]));
+        assert_eq!(interop::vc_add(&mut a, &DMatrix::from_element(3, 2, 1.0)),
+            Err(Error::Dimensions(vec![2, 3], vec![3, 2])));
+
+        let v: Vec<f32> = vec![1.0, 2.0, 3.0];
+        let pointer = v.as_ptr();
+        let mut vector = from_vec(v);
+        interop::vc_mul(&mut vector, &vec![2.0, 2.0, 2.0]).unwrap();
+        assert_eq!(interop::sum(&vector), Ok(12.0));
+        let v = into_vec(vector);
+        assert_eq!(v, vec![2.0, 4.0, 6.0]);
+        assert_eq!(v.as_ptr(), pointer);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn mixed() {
+        // A column vector and a 1-D array share a layout; matrices of the same shape but
+        // different orders don't, and neither do a matrix and the transpose of its storage.
+        let mut v = DVector::from_vec(vec![1.0, 2.0]);
+        interop::vc_add(&mut v, &::ndarray::array![1.0, 1.0]).unwrap();
+        assert_eq!(v.as_slice(), &[2.0, 3.0]);
+
+        let mut m = DMatrix::from_element(2, 3, 1.0);
+        assert_eq!(interop::vc_add(&mut m, &::ndarray::Array2::from_elem((2, 3), 1.0)),
+            Err(Error::Order(Order::ColumnMajor, Order::RowMajor)));
+        assert_eq!(interop::vc_add(&mut m, &::ndarray::Array2::from_elem((3, 2), 1.0)),
+            Err(Error::Dimensions(vec![2, 3], vec![3, 2])));
+
+        let v = DVector::from_vec(vec![3.0f64, 4.0]);
+        let a = ::ndarray::array![1.0f64, 2.0];
+        assert_eq!(interop::norm(&v), Ok(5.0));
+        assert_eq!(interop::dot(&v, &a), Ok(11.0));
+    }
+}
@@ -0,0 +1,91 @@
+use super::{Contiguous, ContiguousMut, Error, Layout, Order};
+use crate::ShapeError;
+use ::ndarray::{Array, Array1, Array2, ArrayBase, Data, DataMut, Dimension, Ix2};
+use std::vec::Vec;
+
+// Arrays are contiguous when they are in standard (row-major) layout. Views produced by
+// transposing or slicing with a step are not, and are reported as `Error::NotContiguous`.
+
+impl<S, D> Contiguous<S::Elem> for ArrayBase<S, D> where
+    S: Data,
+    D: Dimension,
+{
+    fn layout(&self) -> Layout {
+        Layout{ shape: self.shape().to_vec(), order: Order::RowMajor }
+    }
+
+    fn contiguous(&self) -> Result<&[S::Elem], Error> {
+        self.as_slice().ok_or(Error::NotContiguous)
+    }
+}
+
+impl<S, D> ContiguousMut<S::Elem> for ArrayBase<S, D> where
+    S: DataMut,
+    D: Dimension,
+{
+    fn contiguous_mut(&mut self) -> Result<&mut [S::Elem], Error> {
+        self.as_slice_mut().ok_or(Error::NotContiguous)
+    }
+}
+
+// Zero-copy conversions between `Vec` and owned arrays.
+
+pub fn from_vec<T>(v: Vec<T>) -> Array1<T>
+{
+    Array1::from(v)
+}
+
+pub fn from_vec2<T>(v: Vec<T>, rows: usize, columns: usize) -> Result<Array2<T>, Error>
+{
+    let found = v.len();
+    Array::from_shape_vec(Ix2(rows, columns), v)
+        .map_err(|_| ShapeError{ expected: rows * columns, found }.into())
+}
+
+// Reuses the array's allocation when it is in standard layout, and copies otherwise.
+pub fn into_vec<T: Clone, D: Dimension>(a: Array<T, D>) -> Vec<T>
+{
+    if !a.is_standard_layout() {
+        return a.iter().cloned().collect();
+    }
+    let len = a.len();
+    let (mut v, offset) = a.into_raw_vec_and_offset();
+    v.drain(..offset.unwrap_or(0));
+    v.truncate(len);
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interop;
+    use ::ndarray::{array, s};
+
+    #[test]
+    fn arrays() {
+        let mut a = array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let b = from_vec2(vec![1.0; 6], 2, 3).unwrap();
+
+        interop::vc_add(&mut a, &b).unwrap();
+        interop::sc_mul(&mut a, 0.5).unwrap();
+        assert_eq!(a, array![[1.0, 1.5, 2.0], [2.5, 3.0, 3.5]]);
+
+        // Works on mutable views of contiguous rows too.
+        interop::sc_add(&mut a.slice_mut(s![1, ..]), 1.0).unwrap();
+        assert_eq!(a.row(1).to_vec(), vec![3.5, 4.0, 4.5]);
+
+        let t = b.t();
+        assert_eq!(interop::equal(&a, &t), Ok(false));
+        assert_eq!(interop::vc_add(&mut a, &b.clone().into_shape_with_order((3, 2)).unwrap()),
+            Err(Error::Dimensions(vec![2, 3], vec![3, 2])));
+        assert_eq!(interop::vc_add(&mut a, &from_vec2(vec![1.0; 6], 3, 2).unwrap().t()),
+            Err(Error::NotContiguous));
+
+        let v: Vec<f32> = vec![1.0, 2.0, 3.0];
+        let pointer = v.as_ptr();
+        let array = from_vec(v);
+        assert_eq!(interop::sum(&array), Ok(6.0));
+        let v = into_vec(array);
+        assert_eq!(v.as_ptr(), pointer);
+    }
+}
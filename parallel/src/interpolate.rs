@@ -0,0 +1,288 @@
+use crate::{FloatVector, ShapeError};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+// Interpolation of samples `y` taken at strictly increasing positions `x` onto new query
+// points, and integer-factor resampling of uniformly sampled signals.
+
+// What to do with query points outside [x[0], x[n - 1]].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Extrapolate<T> {
+    // Use the value at the nearest end point.
+    Clamp,
+    // Use a fixed value, e.g. NaN.
+    Fill(T),
+    // Continue the first/last piece of the interpolant.
+    Extend,
+    // Fail with `Error::OutOfRange`.
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error<T> {
+    Shape(ShapeError),
+    // `x` is not strictly increasing at this index.
+    NotSorted(usize),
+    // The method needs more samples than were given.
+    TooFewPoints(usize),
+    OutOfRange { index: usize, value: T },
+}
+
+impl<T> From<ShapeError> for Error<T> {
+    fn from(e: ShapeError) -> Self {
+        Error::Shape(e)
+    }
+}
+
+fn check<T: FloatVector>(x: &[T], y: &[T], minimum: usize) -> Result<(), Error<T>>
+{
+    if x.len() != y.len() {
+        return Err(ShapeError{ expected: x.len(), found: y.len() }.into());
+    }
+    if x.len() < minimum {
+        return Err(Error::TooFewPoints(x.len()));
+    }
+    match x.windows(2).position(|w| w[0] >= w[1] || w[1].is_nan()) {
+        Some(i) => Err(Error::NotSorted(i + 1)),
+        None => Ok(()),
+    }
+}
+
+// Evaluates `inside(segment, q)` for every query point, where `segment` is the index of
+// the interval [x[i], x[i + 1]] that contains (or, when extending, is closest to) `q`.
+fn evaluate<T, F>(x: &[T], y: &[T], query: &[T], extrapolate: Extrapolate<T>, inside: F)
+    -> Result<Vec<T>, Error<T>> where
+    T: FloatVector,
+    F: Fn(usize, T) -> T + Sync,
+{
+    let first = x[0];
+    let last = x[x.len() - 1];
+    let segments = x.len().max(2) - 1;
+
+    query.par_iter()
+        .enumerate()
+        .map(|(index, q)| {
+            let q = *q;
+            if q < first || q > last || q.is_nan() {
+                return match extrapolate {
+                    Extrapolate::Clamp if q < first => Ok(y[0]),
+                    Extrapolate::Clamp if q > last => Ok(y[y.len() - 1]),
+                    Extrapolate::Fill(value) => Ok(value),
+                    Extrapolate::Extend if q < first => Ok(inside(0, q)),
+                    Extrapolate::Extend if q > last => Ok(inside(segments - 1, q)),
+                    _ => Err(Error::OutOfRange{ index, value: q }),
+                };
+            }
+            let segment = x.partition_point(|e| *e <= q).clamp(1, segments) - 1;
+            Ok(inside(segment, q))
+        })
+        .collect()
+}
+
+pub fn nearest<T: FloatVector>(x: &[T], y: &[T], query: &[T], extrapolate: Extrapolate<T>)
+    -> Result<Vec<T>, Error<T>>
+{
+    check(x, y, 1)?;
+    let extrapolate = match extrapolate {
+        Extrapolate::Extend => Extrapolate::Clamp,
+        other => other,
+    };
+    evaluate(x, y, query, extrapolate, |i, q| {
+        if x.len() == 1 {
+            y[0]
+        } else if q - x[i] <= x[i + 1] - q {
+            y[i]
+        } else {
+            y[i + 1]
+        }
+    })
+}
+
+pub fn linear<T: FloatVector>(x: &[T], y: &[T], query: &[T], extrapolate: Extrapolate<T>)
+    -> Result<Vec<T>, Error<T>>
+{
+    check(x, y, 2)?;
+    evaluate(x, y, query, extrapolate, |i, q| {
+        let t = (q - x[i]) / (x[i + 1] - x[i]);
+        y[i] + t * (y[i + 1] - y[i])
+    })
+}
+
+// Natural cubic spline (zero second derivative at both ends).
+#[derive(Clone, Debug)]
+pub struct CubicSpline<T> {
+    x: Vec<T>,
+    y: Vec<T>,
+    // Second derivatives at the knots.
+    m: Vec<T>,
+}
+
+impl<T: FloatVector> CubicSpline<T> {
+    pub fn new(x: &[T], y: &[T]) -> Result<Self, Error<T>> {
+        check(x, y, 3)?;
+        let n = x.len();
+        let two = T::from(2).unwrap();
+        let six = T::from(6).unwrap();
+
+        // Tridiagonal system for the interior second derivatives (Thomas algorithm).
+        let mut diagonal = vec![T::one(); n];
+        let mut rhs = vec![T::zero(); n];
+        let mut upper = vec![T::zero(); n];
+        for i in 1..n - 1 {
+            let h0 = x[i] - x[i - 1];
+            let h1 = x[i + 1] - x[i];
+            let lower = h0;
+            diagonal[i] = two * (h0 + h1) - lower * upper[i - 1];
+            upper[i] = h1 / diagonal[i];
+            rhs[i] = (six * ((y[i + 1] - y[i]) / h1 - (y[i] - y[i - 1]) / h0) - lower * rhs[i - 1]) / diagonal[i];
+        }
+
+        let mut m = vec![T::zero(); n];
+        for i in (1..n - 1).rev() {
+            m[i] = rhs[i] - upper[i] * m[i + 1];
+        }
+
+        Ok(CubicSpline{ x: x.to_vec(), y: y.to_vec(), m })
+    }
+
+    pub fn evaluate(&self, query: &[T], extrapolate: Extrapolate<T>) -> Result<Vec<T>, Error<T>> {
+        let (x, y, m) = (&self.x, &self.y, &self.m);
+        let six = T::from(6).unwrap();
+        evaluate(x, y, query, extrapolate, |i, q| {
+            let h = x[i + 1] - x[i];
+            let a = (x[i + 1] - q) / h;
+            let b = (q - x[i]) / h;
+            a * y[i] + b * y[i + 1]
+                + ((a * a * a - a) * m[i] + (b * b * b - b) * m[i + 1]) * h * h / six
+        })
+    }
+}
+
+pub fn cubic<T: FloatVector>(x: &[T], y: &[T], query: &[T], extrapolate: Extrapolate<T>)
+    -> Result<Vec<T>, Error<T>>
+{
+    CubicSpline::new(x, y)?.evaluate(query, extrapolate)
+}
+
+// Windowed-sinc (Hamming) low-pass FIR filter with unit DC gain.
+// `cutoff` is relative to the sampling rate, so 0.5 is the Nyquist frequency.
+pub fn lowpass<T: FloatVector>(taps: usize, cutoff: T) -> Vec<T>
+{
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let two = T::from(2).unwrap();
+    let centre = T::from(taps.max(1) - 1).unwrap() / two;
+    let span = T::from(taps.max(2) - 1).unwrap();
+
+    let mut h: Vec<T> = (0..taps).into_par_iter()
+        .map(|i| {
+            let n = T::from(i).unwrap() - centre;
+            let sinc = if n == T::zero() { two * cutoff } else { (two * pi * cutoff * n).sin() / (pi * n) };
+            let window = T::from(0.54).unwrap() - T::from(0.46).unwrap() * (two * pi * T::from(i).unwrap() / span).cos();
+            sinc * window
+        })
+        .collect();
+
+    let gain = h.iter().fold(T::zero(), |a, b| a + *b);
+    crate::sc_div(&mut h, gain);
+    h
+}
+
+// y[i] = sum_j h[j] * x[i - j + centre], with the signal extended by its edge values.
+fn filter_at<T: FloatVector>(signal: &[T], h: &[T], i: usize) -> T
+{
+    let centre = (h.len() / 2) as isize;
+    let last = signal.len() as isize - 1;
+    h.iter().enumerate().fold(T::zero(), |acc, (j, h)| {
+        let k = (i as isize + centre - j as isize).clamp(0, last) as usize;
+        acc + *h * signal[k]
+    })
+}
+
+// Low-pass filters below the new Nyquist frequency, then keeps every `factor`-th sample.
+pub fn decimate<T: FloatVector>(signal: &[T], factor: usize) -> Vec<T>
+{
+    if factor <= 1 || signal.is_empty() {
+        return signal.to_vec();
+    }
+    let h = lowpass(16 * factor + 1, T::from(0.5 / factor as f64).unwrap());
+    (0..signal.len().div_ceil(factor)).into_par_iter()
+        .map(|i| filter_at(signal, &h, i * factor))
+        .collect()
+}
+
+// Inserts `factor - 1` zeros between samples and removes the resulting images with a
+// low-pass filter, so the output has `factor` times as many samples.
+pub fn upsample<T: FloatVector>(signal: &[T], factor: usize) -> Vec<T>
+{
+    if factor <= 1 || signal.is_empty() {
+        return signal.to_vec();
+    }
+    let h = lowpass(16 * factor + 1, T::from(0.5 / factor as f64).unwrap());
+    let centre = (h.len() / 2) as isize;
+    let last = signal.len() as isize - 1;
+    let gain = T::from(factor).unwrap();
+
+    (0..signal.len() * factor).into_par_iter()
+        .map(|i| {
+            // Only taps that land on an original sample contribute.
+            let mut acc = T::zero();
+            let offset = (i as isize + centre).rem_euclid(factor as isize) as usize;
+            for j in (offset..h.len()).step_by(factor) {
+                let k = ((i as isize + centre - j as isize) / factor as isize).clamp(0, last) as usize;
+                acc += h[j] * signal[k];
+            }
+            gain * acc
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation() {
+        let x: Vec<f64> = vec![0.0, 1.0, 2.0, 4.0];
+        let y = vec![0.0, 2.0, 4.0, 8.0];
+        let q = vec![-1.0, 0.5, 3.0, 4.0, 5.0];
+
+        assert_eq!(linear(&x, &y, &q, Extrapolate::Extend).unwrap(), vec![-2.0, 1.0, 6.0, 8.0, 10.0]);
+        assert_eq!(linear(&x, &y, &q, Extrapolate::Clamp).unwrap(), vec![0.0, 1.0, 6.0, 8.0, 8.0]);
+        assert_eq!(nearest(&x, &y, &q, Extrapolate::Fill(-7.0)).unwrap(), vec![-7.0, 0.0, 4.0, 8.0, -7.0]);
+        assert_eq!(linear(&x, &y, &q, Extrapolate::Error), Err(Error::OutOfRange{ index: 0, value: -1.0 }));
+        assert_eq!(linear(&[0.0, 0.0], &[1.0, 2.0], &q, Extrapolate::Clamp), Err(Error::NotSorted(1)));
+
+        // A natural spline through a straight line is the line itself.
+        let spline = cubic(&x, &y, &[0.25, 1.5, 3.9], Extrapolate::Error).unwrap();
+        for (s, e) in spline.iter().zip(&[0.5, 3.0, 7.8]) {
+            assert!((s - e).abs() < 1e-12);
+        }
+
+        let x: Vec<f64> = (0..=20).map(|i| i as f64 * 0.25).collect();
+        let y: Vec<f64> = x.iter().map(|e| e.sin()).collect();
+        let spline = CubicSpline::new(&x, &y).unwrap();
+        let value = spline.evaluate(&[2.1], Extrapolate::Error).unwrap();
+        assert!((value[0] - 2.1f64.sin()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn resampling() {
+        // A slow sine survives decimation and upsampling; a tone above the new Nyquist
+        // frequency is removed by the anti-aliasing filter.
+        let slow: Vec<f64> = (0..1000).map(|i| (i as f64 * 0.01).sin()).collect();
+        let fast: Vec<f64> = (0..1000).map(|i| (i as f64 * 0.45 * std::f64::consts::PI).sin()).collect();
+        let mixed: Vec<f64> = slow.iter().zip(&fast).map(|(a, b)| a + b).collect();
+
+        let decimated = decimate(&mixed, 4);
+        assert_eq!(decimated.len(), 250);
+        for (i, e) in decimated.iter().enumerate().skip(20).take(200) {
+            assert!((e - slow[4 * i]).abs() < 0.02);
+        }
+
+        let upsampled = upsample(&slow, 3);
+        assert_eq!(upsampled.len(), 3000);
+        for (i, e) in upsampled.iter().enumerate().skip(100).take(2800) {
+            assert!((e - (i as f64 * 0.01 / 3.0).sin()).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::distance::Metric;
+use crate::{FloatVector, ShapeError};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+// k-means clustering of the rows of `data`.
+//
+// Results only depend on the seed: assignments are computed in parallel, but partial
+// sums are always formed over the same fixed chunks and combined in the same order,
+// so the thread count does not change the floating point result.
+
+const CHUNK: usize = 4096;
+
+#[derive(Clone, Debug)]
+pub struct Options<T> {
+    pub max_iterations: usize,
+    // Stop once no centroid moves further than this (Euclidean distance).
+    pub tolerance: T,
+    pub seed: u64,
+}
+
+impl<T: FloatVector> Default for Options<T> {
+    fn default() -> Self {
+        Options {
+            max_iterations: 300,
+            tolerance: T::from(1e-6).unwrap(),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Shape(ShapeError),
+    // Fewer rows than requested clusters.
+    NotEnoughPoints(usize),
+    // Asked for zero clusters.
+    InvalidK,
+}
+
+impl From<ShapeError> for Error {
+    fn from(e: ShapeError) -> Self {
+        Error::Shape(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Clustering<T> {
+    pub centroids: Vec<Vec<T>>,
+    pub labels: Vec<usize>,
+    // Sum of squared distances from each row to its centroid.
+    pub inertia: T,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+// SplitMix64, so results don't depend on an external generator's versioning.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1).
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.uniform() * n as f64) as usize
+    }
+}
+
+fn check<T>(data: &[Vec<T>], k: usize) -> Result<(), Error>
+{
+    if k == 0 {
+        return Err(Error::InvalidK);
+    }
+    if data.len() < k {
+        return Err(Error::NotEnoughPoints(data.len()));
+    }
+    let expected = data[0].len();
+    match data.iter().find(|row| row.len() != expected) {
+        Some(row) => Err(Error::Shape(ShapeError{ expected, found: row.len() })),
+        None => Ok(()),
+    }
+}
+
+fn closest<T: FloatVector>(row: &[T], centroids: &[Vec<T>]) -> (usize, T)
+{
+    centroids.iter()
+        .map(|c| Metric::SquaredEuclidean.distance(row, c))
+        .enumerate()
+        .fold((0, T::infinity()), |best, (i, d)| if d < best.1 { (i, d) } else { best })
+}
+
+fn sum_in_order<T: FloatVector>(partials: Vec<T>) -> T
+{
+    partials.into_iter().fold(T::zero(), |a, b| a + b)
+}
+
+// k-means++: each further centroid is drawn with probability proportional to its squared
+// distance from the nearest centroid chosen so far.
+fn initialise<T: FloatVector>(data: &[Vec<T>], k: usize, random: &mut Random) -> Vec<Vec<T>>
+{
+    let mut centroids = vec![data[random.below(data.len())].clone()];
+    let mut weights: Vec<T> = data.par_iter()
+        .map(|row| Metric::SquaredEuclidean.distance(row, &centroids[0]))
+        .collect();
+
+    while centroids.len() < k {
+        let total = sum_in_order(weights.par_chunks(CHUNK).map(|c| c.iter().fold(T::zero(), |a, b| a + *b)).collect());
+        let next = if total > T::zero() {
+            let target = T::from(random.uniform()).unwrap() * total;
+            let mut cumulative = T::zero();
+            weights.iter().position(|w| { cumulative += *w; cumulative > target }).unwrap_or(data.len() - 1)
+        } else {
+            random.below(data.len())
+        };
+
+        centroids.push(data[next].clone());
+        let centroid = &centroids[centroids.len() - 1];
+        weights.par_iter_mut().zip(data).for_each(|(w, row)| {
+            *w = w.min(Metric::SquaredEuclidean.distance(row, centroid));
+        });
+    }
+
+    centroids
+}
+
+fn finish<T: FloatVector>(data: &[Vec<T>], centroids: Vec<Vec<T>>, iterations: usize, converged: bool) -> Clustering<T>
+{
+    let assigned: Vec<(usize, T)> = data.par_iter().map(|row| closest(row, &centroids)).collect();
+    let inertia = sum_in_order(assigned.par_chunks(CHUNK).map(|c| c.iter().fold(T::zero(), |a, b| a + b.1)).collect());
+    Clustering{
+        centroids,
+        labels: assigned.into_iter().map(|(label, _)| label).collect(),
+        inertia,
+        iterations,
+        converged,
+    }
+}
+
+fn max_shift<T: FloatVector>(a: &[Vec<T>], b: &[Vec<T>]) -> T
+{
+    a.iter().zip(b).fold(T::zero(), |m, (a, b)| m.max(Metric::Euclidean.distance(a, b)))
+}
+
+// Lloyd's algorithm with k-means++ initialisation.
+pub fn kmeans<T: FloatVector>(data: &[Vec<T>], k: usize, options: &Options<T>) -> Result<Clustering<T>, Error>
+{
+    check(data, k)?;
+    let dimension = data[0].len();
+    let mut random = Random(options.seed);
+    let mut centroids = initialise(data, k, &mut random);
+
+    for iteration in 1..=options.max_iterations {
+        let labels: Vec<usize> = data.par_iter().map(|row| closest(row, &centroids).0).collect();
+
+        // Per-chunk sums and counts, combined in chunk order.
+        let partials: Vec<(Vec<Vec<T>>, Vec<usize>)> = data.par_chunks(CHUNK)
+            .zip(labels.par_chunks(CHUNK))
+            .map(|(rows, labels)| {
+                let mut sums = vec![vec![T::zero(); dimension]; k];
+                let mut counts = vec![0; k];
+                for (row, label) in rows.iter().zip(labels) {
+                    for (s, e) in sums[*label].iter_mut().zip(row) {
+                        *s += *e;
+                    }
+                    counts[*label] += 1;
+                }
+                (sums, counts)
+            })
+            .collect();
+
+        let mut sums = vec![vec![T::zero(); dimension]; k];
+        let mut counts = vec![0usize; k];
+        for (partial_sums, partial_counts) in partials {
+            for c in 0..k {
+                for (s, e) in sums[c].iter_mut().zip(&partial_sums[c]) {
+                    *s += *e;
+                }
+                counts[c] += partial_counts[c];
+            }
+        }
+
+        // Empty clusters keep their previous centroid.
+        let updated: Vec<Vec<T>> = sums.into_iter()
+            .zip(&counts)
+            .zip(&centroids)
+            .map(|((mut sum, count), previous)| {
+                if *count == 0 {
+                    return previous.clone();
+                }
+                crate::sc_div(&mut sum, T::from(*count).unwrap());
+                sum
+            })
+            .collect();
+
+        let shift = max_shift(&centroids, &updated);
+        centroids = updated;
+        if shift <= options.tolerance {
+            return Ok(finish(data, centroids, iteration, true));
+        }
+    }
+
+    Ok(finish(data, centroids, options.max_iterations, false))
+}
+
+// Mini-batch k-means: every iteration draws `batch_size` rows and moves their centroids
+// towards them with a per-centroid learning rate of 1 / (rows seen so far).
+pub fn mini_batch_kmeans<T: FloatVector>(data: &[Vec<T>], k: usize, batch_size: usize, options: &Options<T>)
+    -> Result<Clustering<T>, Error>
+{
+    check(data, k)?;
+    let mut random = Random(options.seed);
+    let mut centroids = initialise(data, k, &mut random);
+    let mut seen = vec![0usize; k];
+
+    for iteration in 1..=options.max_iterations {
+        let batch: Vec<usize> = (0..batch_size.max(1)).map(|_| random.below(data.len())).collect();
+        let labels: Vec<usize> = batch.par_iter().map(|i| closest(&data[*i], &centroids).0).collect();
+
+        let previous = centroids.clone();
+        for (i, label) in batch.iter().zip(labels) {
+            seen[label] += 1;
+            let rate = T::one() / T::from(seen[label]).unwrap();
+            for (c, e) in centroids[label].iter_mut().zip(&data[*i]) {
+                *c += rate * (*e - *c);
+            }
+        }
+
+        if max_shift(&previous, &centroids) <= options.tolerance {
+            return Ok(finish(data, centroids, iteration, true));
+        }
+    }
+
+    Ok(finish(data, centroids, options.max_iterations, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blobs() -> Vec<Vec<f64>> {
+        let mut random = Random(42);
+        let centres = [[0.0, 0.0], [10.0, 10.0], [-10.0, 10.0]];
+        (0..300)
+            .map(|i| {
+                let c = centres[i % 3];
+                vec![c[0] + random.uniform() - 0.5, c[1] + random.uniform() - 0.5]
+            })
+            .collect()
+    }
+
+    fn sorted(mut centroids: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+        centroids.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        centroids
+    }
+
+    #[test]
+    fn lloyd() {
+        let data = blobs();
+        let options = Options{ seed: 7, ..Options::default() };
+        let result = kmeans(&data, 3, &options).unwrap();
+
+        assert!(result.converged);
+        for (centroid, expected) in sorted(result.centroids.clone()).iter().zip(&[[-10.0, 10.0], [0.0, 0.0], [10.0, 10.0]]) {
+            assert!(Metric::Euclidean.distance(centroid, expected) < 0.2);
+        }
+        for i in 0..3 {
+            assert!(result.labels.iter().skip(i).step_by(3).all(|l| *l == result.labels[i]));
+        }
+
+        // Same seed, same result.
+        let again = kmeans(&data, 3, &options).unwrap();
+        assert_eq!(again.centroids, result.centroids);
+        assert_eq!(again.labels, result.labels);
+
+        assert_eq!(kmeans(&data[..2], 3, &options).unwrap_err(), Error::NotEnoughPoints(2));
+        assert_eq!(kmeans(&data, 0, &options).unwrap_err(), Error::InvalidK);
+    }
+
+    #[test]
+    fn mini_batch() {
+        let data = blobs();
+        let options = Options{ seed: 3, max_iterations: 200, tolerance: 0.0 };
+        let result = mini_batch_kmeans(&data, 3, 32, &options).unwrap();
+
+        for (centroid, expected) in sorted(result.centroids.clone()).iter().zip(&[[-10.0, 10.0], [0.0, 0.0], [10.0, 10.0]]) {
+            assert!(Metric::Euclidean.distance(centroid, expected) < 0.5);
+        }
+        assert_eq!(mini_batch_kmeans(&data, 3, 32, &options).unwrap().centroids, result.centroids);
+    }
+}
@@ -1,7 +1,16 @@
+pub mod autodiff;
+pub mod distance;
+pub mod fit;
+pub mod interop;
+pub mod interpolate;
+pub mod kmeans;
+pub mod ode;
+pub mod precision;
+pub mod sort;
+
 use num_traits::Float;
 use rayon::prelude::*;
 use std::ops::{AddAssign, DivAssign, MulAssign, SubAssign};
-use std::vec::Vec;
 
 pub trait FloatVector:
     Float + Default + AddAssign + DivAssign + MulAssign + SubAssign + Send + Sync
@@ -11,58 +20,91 @@ impl<T> FloatVector for T where
     T: Float + Default + AddAssign + DivAssign + MulAssign + SubAssign + Send + Sync
 {}
 
-pub fn default<T: FloatVector>(v: &mut Vec<T>) {
+// Returned by the vector-vector kernels when the operands differ in length.
+// The destination is left untouched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeError {
+    pub expected: usize,
+    pub found: usize,
+}
+
+impl std::fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected a vector of length {}, found length {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+pub(crate) fn check_shape<A, B>(a: &[A], b: &[B]) -> Result<(), ShapeError>
+{
+    if a.len() == b.len() {
+        Ok(())
+    } else {
+        Err(ShapeError{ expected: a.len(), found: b.len() })
+    }
+}
+
+pub fn default<T: FloatVector>(v: &mut [T]) {
     v.par_iter_mut().for_each(|e| *e = Default::default())
 }
 
-pub fn equal<T: FloatVector>(a: &Vec<T>, b: &Vec<T>) -> bool
+pub fn equal<T: FloatVector>(a: &[T], b: &[T]) -> bool
 {
-    a.par_iter().zip(b).all(|(a, b)| *a == *b)
+    a.len() == b.len() && a.par_iter().zip(b).all(|(a, b)| *a == *b)
 }
 
-pub fn set<T: FloatVector>(v: &mut Vec<T>, s: T)
+pub fn set<T: FloatVector>(v: &mut [T], s: T)
 {
     v.par_iter_mut().for_each(|e| *e = s)
 }
 
-pub fn sc_add<T: FloatVector>(v: &mut Vec<T>, s: T)
+pub fn sc_add<T: FloatVector>(v: &mut [T], s: T)
 {
     v.par_iter_mut().for_each(|e| *e += s)
 }
 
-pub fn sc_div<T: FloatVector>(v: &mut Vec<T>, s: T)
+pub fn sc_div<T: FloatVector>(v: &mut [T], s: T)
 {
     v.par_iter_mut().for_each(|e| *e /= s)
 }
 
-pub fn sc_mul<T: FloatVector>(v: &mut Vec<T>, s: T)
+pub fn sc_mul<T: FloatVector>(v: &mut [T], s: T)
 {
     v.par_iter_mut().for_each(|e| *e *= s)
 }
 
-pub fn sc_sub<T: FloatVector>(v: &mut Vec<T>, s: T)
+pub fn sc_sub<T: FloatVector>(v: &mut [T], s: T)
 {
     v.par_iter_mut().for_each(|e| *e -= s)
 }
 
-pub fn vc_add<T: FloatVector>(a: &mut Vec<T>, b: &Vec<T>)
+pub fn vc_add<T: FloatVector>(a: &mut [T], b: &[T]) -> Result<(), ShapeError>
 {
-    a.par_iter_mut().zip(b).for_each(|(a, b)| *a += *b)
+    check_shape(a, b)?;
+    a.par_iter_mut().zip(b).for_each(|(a, b)| *a += *b);
+    Ok(())
 }
 
-pub fn vc_div<T: FloatVector>(a: &mut Vec<T>, b: &Vec<T>)
+pub fn vc_div<T: FloatVector>(a: &mut [T], b: &[T]) -> Result<(), ShapeError>
 {
-    a.par_iter_mut().zip(b).for_each(|(a, b)| *a /= *b)
+    check_shape(a, b)?;
+    a.par_iter_mut().zip(b).for_each(|(a, b)| *a /= *b);
+    Ok(())
 }
 
-pub fn vc_mul<T: FloatVector>(a: &mut Vec<T>, b: &Vec<T>)
+pub fn vc_mul<T: FloatVector>(a: &mut [T], b: &[T]) -> Result<(), ShapeError>
 {
-    a.par_iter_mut().zip(b).for_each(|(a, b)| *a *= *b)
+    check_shape(a, b)?;
+    a.par_iter_mut().zip(b).for_each(|(a, b)| *a *= *b);
+    Ok(())
 }
 
-pub fn vc_sub<T: FloatVector>(a: &mut Vec<T>, b: &Vec<T>)
+pub fn vc_sub<T: FloatVector>(a: &mut [T], b: &[T]) -> Result<(), ShapeError>
 {
-    a.par_iter_mut().zip(b).for_each(|(a, b)| *a -= *b)
+    check_shape(a, b)?;
+    a.par_iter_mut().zip(b).for_each(|(a, b)| *a -= *b);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -86,7 +128,7 @@ mod tests {
         let     c = vec![vec![2.0, 4.0, 6.0]; 3];
 
         for i in 0..a.len() {
-            crate::vc_add(&mut a[i], &b[i]);
+            crate::vc_add(&mut a[i], &b[i]).unwrap();
         }
 
         assert_eq!(a, c);
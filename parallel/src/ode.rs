@@ -0,0 +1,366 @@
+use crate::FloatVector;
+use rayon::prelude::*;
+use std::vec::Vec;
+
+// A system of first order ODEs is written as `f(t, y, dydt)`, where `f` stores
+// the derivative of the state `y` at time `t` into `dydt`.
+
+fn constant<T: FloatVector>(x: f64) -> T
+{
+    T::from(x).expect("constant is not representable")
+}
+
+// out = y + h * sum(coefficients[j] * k[j])
+fn combine<T: FloatVector>(out: &mut [T], y: &[T], h: T, k: &[&Vec<T>], coefficients: &[T])
+{
+    out.par_iter_mut().enumerate().for_each(|(i, e)| {
+        let mut sum = T::zero();
+        for (k, c) in k.iter().zip(coefficients) {
+            sum += *c * k[i];
+        }
+        *e = y[i] + h * sum;
+    })
+}
+
+// Explicit Euler: advances `y` in place by `steps` steps of size `h` and returns the final time.
+pub fn euler<T, F>(f: F, t0: T, y: &mut [T], h: T, steps: usize) -> T where
+    T: FloatVector,
+    F: Fn(T, &[T], &mut [T]),
+{
+    let mut t = t0;
+    let mut dydt = vec![T::zero(); y.len()];
+    for _ in 0..steps {
+        f(t, y, &mut dydt);
+        crate::sc_mul(&mut dydt, h);
+        crate::vc_add(y, &dydt).expect("derivative has the same length as the state");
+        t += h;
+    }
+    t
+}
+
+// Classic fourth order Runge-Kutta: advances `y` in place and returns the final time.
+pub fn rk4<T, F>(f: F, t0: T, y: &mut [T], h: T, steps: usize) -> T where
+    T: FloatVector,
+    F: Fn(T, &[T], &mut [T]),
+{
+    let half: T = constant(0.5);
+    let sixth: T = constant(1.0 / 6.0);
+    let third: T = constant(1.0 / 3.0);
+
+    let mut t = t0;
+    let mut k1 = vec![T::zero(); y.len()];
+    let mut k2 = vec![T::zero(); y.len()];
+    let mut k3 = vec![T::zero(); y.len()];
+    let mut k4 = vec![T::zero(); y.len()];
+    let mut tmp = vec![T::zero(); y.len()];
+    for _ in 0..steps {
+        f(t, y, &mut k1);
+        combine(&mut tmp, y, h, &[&k1], &[half]);
+        f(t + half * h, &tmp, &mut k2);
+        combine(&mut tmp, y, h, &[&k2], &[half]);
+        f(t + half * h, &tmp, &mut k3);
+        combine(&mut tmp, y, h, &[&k3], &[T::one()]);
+        f(t + h, &tmp, &mut k4);
+        combine(&mut tmp, y, h, &[&k1, &k2, &k3, &k4], &[sixth, third, third, sixth]);
+        y.copy_from_slice(&tmp);
+        t += h;
+    }
+    t
+}
+
+#[derive(Clone, Debug)]
+pub struct Options<T> {
+    pub rtol: T,
+    pub atol: T,
+    pub initial_step: T,
+    pub min_step: T,
+    pub max_step: T,
+}
+
+impl<T: FloatVector> Default for Options<T> {
+    fn default() -> Self {
+        Options {
+            rtol: constant(1e-6),
+            atol: constant(1e-9),
+            initial_step: constant(1e-3),
+            min_step: constant(1e-12),
+            max_step: T::infinity(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error<T> {
+    // The error estimate could not be satisfied without going below `min_step` at time `t`,
+    // or without the step underflowing. A step whose error estimate isn't finite (e.g. the
+    // RHS returned NaN) never satisfies it.
+    StepSizeTooSmall(T),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+pub type Condition<'a, T> = Box<dyn Fn(T, &[T]) -> T + 'a>;
+pub type Callback<'a, T> = Box<dyn FnMut(T, &[T]) -> Control + 'a>;
+
+// An event fires when `condition(t, y)` changes sign. The crossing is located on the
+// dense output and passed to `callback`, which decides whether integration continues.
+pub struct Event<'a, T> {
+    condition: Condition<'a, T>,
+    callback: Callback<'a, T>,
+}
+
+impl<'a, T> Event<'a, T> {
+    pub fn new<C, A>(condition: C, callback: A) -> Self where
+        C: Fn(T, &[T]) -> T + 'a,
+        A: FnMut(T, &[T]) -> Control + 'a,
+    {
+        Event{ condition: Box::new(condition), callback: Box::new(callback) }
+    }
+}
+
+// Accepted steps of an adaptive integration. `events` holds (event index, time) pairs.
+#[derive(Clone, Debug)]
+pub struct Solution<T> {
+    pub t: Vec<T>,
+    pub y: Vec<Vec<T>>,
+    pub dydt: Vec<Vec<T>>,
+    pub events: Vec<(usize, T)>,
+}
+
+impl<T: FloatVector> Solution<T> {
+    // Dense output: cubic Hermite interpolation between accepted steps.
+    pub fn sample(&self, t: T) -> Option<Vec<T>> {
+        let first = *self.t.first()?;
+        let last = *self.t.last()?;
+        if t < first || t > last {
+            return None;
+        }
+
+        let i = match self.t.iter().position(|e| *e >= t) {
+            Some(0) | None => return Some(self.y[0].clone()),
+            Some(i) => i - 1,
+        };
+
+        Some(hermite(
+            self.t[i], &self.y[i], &self.dydt[i],
+            self.t[i + 1], &self.y[i + 1], &self.dydt[i + 1],
+            t))
+    }
+}
+
+fn hermite<T: FloatVector>(t0: T, y0: &[T], f0: &[T], t1: T, y1: &[T], f1: &[T], t: T) -> Vec<T>
+{
+    let two: T = constant(2.0);
+    let three: T = constant(3.0);
+
+    let h = t1 - t0;
+    let s = (t - t0) / h;
+    let h00 = two * s * s * s - three * s * s + T::one();
+    let h10 = s * s * s - two * s * s + s;
+    let h01 = three * s * s - two * s * s * s;
+    let h11 = s * s * s - s * s;
+
+    (0..y0.len()).into_par_iter()
+        .map(|i| h00 * y0[i] + h10 * h * f0[i] + h01 * y1[i] + h11 * h * f1[i])
+        .collect()
+}
+
+// Adaptive Dormand-Prince 5(4) integration of `y0` from `t0` to `t1`.
+pub fn dopri5<T, F>(
+    f: F,
+    t0: T,
+    t1: T,
+    y0: &[T],
+    options: &Options<T>,
+    events: &mut [Event<T>],
+) -> Result<Solution<T>, Error<T>> where
+    T: FloatVector,
+    F: Fn(T, &[T], &mut [T]),
+{
+    let c: [T; 5] = [constant(1.0 / 5.0), constant(3.0 / 10.0), constant(4.0 / 5.0), constant(8.0 / 9.0), T::one()];
+    let a2: [T; 1] = [constant(1.0 / 5.0)];
+    let a3: [T; 2] = [constant(3.0 / 40.0), constant(9.0 / 40.0)];
+    let a4: [T; 3] = [constant(44.0 / 45.0), constant(-56.0 / 15.0), constant(32.0 / 9.0)];
+    let a5: [T; 4] = [constant(19372.0 / 6561.0), constant(-25360.0 / 2187.0), constant(64448.0 / 6561.0), constant(-212.0 / 729.0)];
+    let a6: [T; 5] = [constant(9017.0 / 3168.0), constant(-355.0 / 33.0), constant(46732.0 / 5247.0), constant(49.0 / 176.0), constant(-5103.0 / 18656.0)];
+    let b: [T; 6] = [constant(35.0 / 384.0), T::zero(), constant(500.0 / 1113.0), constant(125.0 / 192.0), constant(-2187.0 / 6784.0), constant(11.0 / 84.0)];
+    let e: [T; 7] = [constant(71.0 / 57600.0), T::zero(), constant(-71.0 / 16695.0), constant(71.0 / 1920.0), constant(-17253.0 / 339200.0), constant(22.0 / 525.0), constant(-1.0 / 40.0)];
+
+    let n = y0.len();
+    let mut k: Vec<Vec<T>> = vec![vec![T::zero(); n]; 7];
+    let mut tmp = vec![T::zero(); n];
+    let mut error = vec![T::zero(); n];
+    let zero = vec![T::zero(); n];
+
+    let mut t = t0;
+    let mut y = y0.to_vec();
+    let mut h = options.initial_step.min(options.max_step).min(t1 - t0);
+    f(t, &y, &mut k[0]);
+
+    let mut solution = Solution{
+        t: vec![t],
+        y: vec![y.clone()],
+        dydt: vec![k[0].clone()],
+        events: Vec::new(),
+    };
+    let mut g: Vec<T> = events.iter().map(|event| (event.condition)(t, &y)).collect();
+
+    while t < t1 {
+        h = h.min(t1 - t);
+
+        combine(&mut tmp, &y, h, &[&k[0]], &a2);
+        f(t + c[0] * h, &tmp, &mut k[1]);
+        combine(&mut tmp, &y, h, &[&k[0], &k[1]], &a3);
+        f(t + c[1] * h, &tmp, &mut k[2]);
+        combine(&mut tmp, &y, h, &[&k[0], &k[1], &k[2]], &a4);
+        f(t + c[2] * h, &tmp, &mut k[3]);
+        combine(&mut tmp, &y, h, &[&k[0], &k[1], &k[2], &k[3]], &a5);
+        f(t + c[3] * h, &tmp, &mut k[4]);
+        combine(&mut tmp, &y, h, &[&k[0], &k[1], &k[2], &k[3], &k[4]], &a6);
+        f(t + c[4] * h, &tmp, &mut k[5]);
+        combine(&mut tmp, &y, h, &[&k[0], &k[1], &k[2], &k[3], &k[4], &k[5]], &b);
+        f(t + h, &tmp, &mut k[6]);
+
+        let stages: Vec<&Vec<T>> = k.iter().collect();
+        combine(&mut error, &zero, h, &stages, &e);
+
+        let norm = (error.par_iter()
+            .zip(&y)
+            .zip(&tmp)
+            .map(|((e, y0), y1)| {
+                let scale = options.atol + options.rtol * y0.abs().max(y1.abs());
+                (*e / scale) * (*e / scale)
+            })
+            .reduce(T::zero, |a, b| a + b) / T::from(n.max(1)).unwrap()).sqrt();
+
+        let factor = if norm == T::zero() {
+            constant(5.0)
+        } else if !norm.is_finite() {
+            constant(0.2)
+        } else {
+            (constant::<T>(0.9) * norm.powf(constant(-0.2))).max(constant(0.2)).min(constant(5.0))
+        };
+
+        if !norm.is_finite() || norm > T::one() {
+            h *= factor;
+            if h < options.min_step || t + h == t {
+                return Err(Error::StepSizeTooSmall(t));
+            }
+            continue;
+        }
+
+        let t_next = t + h;
+        let mut stop = false;
+        for (index, event) in events.iter_mut().enumerate() {
+            let g_next = (event.condition)(t_next, &tmp);
+            if g[index] * g_next < T::zero() || (g_next == T::zero() && g[index] != T::zero()) {
+                let (mut lo, mut hi) = (t, t_next);
+                for _ in 0..64 {
+                    let mid = (lo + hi) / constant(2.0);
+                    let y_mid = hermite(t, &y, &k[0], t_next, &tmp, &k[6], mid);
+                    if g[index] * (event.condition)(mid, &y_mid) > T::zero() {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                let y_event = hermite(t, &y, &k[0], t_next, &tmp, &k[6], hi);
+                solution.events.push((index, hi));
+                if (event.callback)(hi, &y_event) == Control::Stop {
+                    let mut dydt = vec![T::zero(); n];
+                    f(hi, &y_event, &mut dydt);
+                    solution.t.push(hi);
+                    solution.y.push(y_event);
+                    solution.dydt.push(dydt);
+                    stop = true;
+                    break;
+                }
+            }
+            g[index] = g_next;
+        }
+        if stop {
+            return Ok(solution);
+        }
+
+        t = t_next;
+        std::mem::swap(&mut y, &mut tmp);
+        k.swap(0, 6);
+        solution.t.push(t);
+        solution.y.push(y.clone());
+        solution.dydt.push(k[0].clone());
+
+        h = (h * factor).min(options.max_step);
+    }
+
+    Ok(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decay(_: f64, y: &[f64], dydt: &mut [f64]) {
+        for (d, y) in dydt.iter_mut().zip(y) {
+            *d = -*y;
+        }
+    }
+
+    #[test]
+    fn fixed_step() {
+        let mut a = vec![1.0, 2.0];
+        let t = euler(decay, 0.0, &mut a, 0.001, 1000);
+        assert!((t - 1.0f64).abs() < 1e-9);
+        assert!((a[0] - (-1.0f64).exp()).abs() < 1e-3);
+
+        let mut b = vec![1.0, 2.0];
+        rk4(decay, 0.0, &mut b, 0.1, 10);
+        assert!((b[0] - (-1.0f64).exp()).abs() < 1e-6);
+        assert!((b[1] - 2.0 * (-1.0f64).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn adaptive_step() {
+        let solution = dopri5(decay, 0.0, 2.0, &[1.0], &Options::default(), &mut []).unwrap();
+        assert_eq!(*solution.t.last().unwrap(), 2.0);
+        assert!((solution.y.last().unwrap()[0] - (-2.0f64).exp()).abs() < 1e-6);
+
+        let y = solution.sample(0.5).unwrap();
+        assert!((y[0] - (-0.5f64).exp()).abs() < 1e-5);
+        assert_eq!(solution.sample(3.0), None);
+
+        // NaNs are rejected rather than spread through the solution, even without a
+        // minimum step:
+        let nan = |_: f64, _: &[f64], dydt: &mut [f64]| dydt[0] = f64::NAN;
+        assert_eq!(dopri5(nan, 0.0, 1.0, &[1.0], &Options::default(), &mut []).unwrap_err(),
+            Error::StepSizeTooSmall(0.0));
+        let options = Options{ min_step: 0.0, ..Options::default() };
+        assert_eq!(dopri5(nan, 0.0, 1.0, &[1.0], &options, &mut []).unwrap_err(), Error::StepSizeTooSmall(0.0));
+    }
+
+    #[test]
+    fn events() {
+        // A ball dropped from 10m; y = [height, velocity].
+        let gravity = |_: f64, y: &[f64], dydt: &mut [f64]| {
+            dydt[0] = y[1];
+            dydt[1] = -9.81;
+        };
+
+        let mut impacts = Vec::new();
+        let mut ground = [Event::new(
+            |_, y: &[f64]| y[0],
+            |t, _: &[f64]| { impacts.push(t); Control::Stop })];
+
+        let solution = dopri5(gravity, 0.0, 10.0, &[10.0, 0.0], &Options::default(), &mut ground).unwrap();
+        drop(ground);
+
+        let expected = (2.0 * 10.0 / 9.81f64).sqrt();
+        assert_eq!(impacts.len(), 1);
+        assert!((impacts[0] - expected).abs() < 1e-6);
+        assert_eq!(solution.events, vec![(0, impacts[0])]);
+        assert!((*solution.t.last().unwrap() - expected).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{check_shape, FloatVector, ShapeError};
+use rayon::prelude::*;
+use std::vec::Vec;
+
+pub use half::{bf16, f16};
+
+// Element types that are stored in (at most) single precision.
+// Reductions widen every element to f32 before accumulating, otherwise a sum of
+// f16 values stops growing as soon as it reaches 2048.
+pub trait Storage: FloatVector {
+    fn widen(self) -> f32;
+    fn narrow(v: f32) -> Self;
+}
+
+impl Storage for f16 {
+    fn widen(self) -> f32 { self.to_f32() }
+    fn narrow(v: f32) -> Self { f16::from_f32(v) }
+}
+
+impl Storage for bf16 {
+    fn widen(self) -> f32 { self.to_f32() }
+    fn narrow(v: f32) -> Self { bf16::from_f32(v) }
+}
+
+impl Storage for f32 {
+    fn widen(self) -> f32 { self }
+    fn narrow(v: f32) -> Self { v }
+}
+
+pub fn sum<T: Storage>(v: &[T]) -> f32
+{
+    v.par_iter().map(|e| e.widen()).sum()
+}
+
+pub fn dot<T: Storage>(a: &[T], b: &[T]) -> Result<f32, ShapeError>
+{
+    check_shape(a, b)?;
+    Ok(a.par_iter().zip(b).map(|(a, b)| a.widen() * b.widen()).sum())
+}
+
+// NaN for an empty slice.
+pub fn mean<T: Storage>(v: &[T]) -> f32
+{
+    sum(v) / v.len() as f32
+}
+
+pub fn norm<T: Storage>(v: &[T]) -> f32
+{
+    dot(v, v).expect("a vector has the same length as itself").sqrt()
+}
+
+pub fn convert<A: Storage, B: Storage>(src: &[A], dst: &mut [B]) -> Result<(), ShapeError>
+{
+    check_shape(dst, src)?;
+    dst.par_iter_mut().zip(src).for_each(|(d, s)| *d = B::narrow(s.widen()));
+    Ok(())
+}
+
+pub fn widen<T: Storage>(v: &[T]) -> Vec<f32>
+{
+    v.par_iter().map(|e| e.widen()).collect()
+}
+
+pub fn narrow<T: Storage>(v: &[f32]) -> Vec<T>
+{
+    v.par_iter().map(|e| T::narrow(*e)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulate_in_single_precision() {
+        let v = vec![f16::ONE; 4096];
+
+        // Accumulating in f16 saturates at 2048:
+        let naive = v.iter().fold(f16::ZERO, |acc, e| acc + *e);
+        assert_eq!(naive, f16::from_f32(2048.0));
+
+        assert_eq!(sum(&v), 4096.0);
+        assert_eq!(mean(&v), 1.0);
+        assert_eq!(dot(&v, &v), Ok(4096.0));
+        assert_eq!(norm(&v), 64.0);
+
+        assert_eq!(dot(&v, &v[1..]), Err(ShapeError{ expected: 4096, found: 4095 }));
+        assert!(mean::<f16>(&[]).is_nan());
+    }
+
+    #[test]
+    fn conversion() {
+        let a = vec![0.5f32, 1.0, 1.5, 3.0];
+        let mut b = vec![bf16::ZERO; 4];
+        let mut c = vec![f16::ZERO; 4];
+
+        convert(&a, &mut b).unwrap();
+        convert(&b, &mut c).unwrap();
+
+        assert_eq!(widen(&c), a);
+        assert_eq!(narrow::<bf16>(&a), b);
+
+        // Element-wise kernels work on half-precision storage directly:
+        crate::sc_mul(&mut c, f16::from_f32(2.0));
+        assert_eq!(widen(&c), vec![1.0, 2.0, 3.0, 6.0]);
+
+        // The destination is left untouched when the lengths differ:
+        assert_eq!(convert(&a[..3], &mut c), Err(ShapeError{ expected: 4, found: 3 }));
+        assert_eq!(widen(&c), vec![1.0, 2.0, 3.0, 6.0]);
+    }
+}
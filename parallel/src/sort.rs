@@ -0,0 +1,160 @@
+use crate::FloatVector;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::vec::Vec;
+
+// Total order over floats: -inf < ... < -0.0 < +0.0 < ... < +inf < NaN.
+// All NaNs compare equal to each other, regardless of sign or payload.
+pub fn total_cmp<T: FloatVector>(a: &T, b: &T) -> Ordering
+{
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => match a.partial_cmp(b) {
+            Some(Ordering::Equal) => a.is_sign_positive().cmp(&b.is_sign_positive()),
+            Some(ordering) => ordering,
+            None => unreachable!(),
+        },
+    }
+}
+
+pub fn sort<T: FloatVector>(v: &mut [T])
+{
+    v.par_sort_unstable_by(total_cmp)
+}
+
+// Indices that would sort `v`. Equal elements keep their original relative order.
+pub fn argsort<T: FloatVector>(v: &[T]) -> Vec<usize>
+{
+    let mut indices: Vec<usize> = (0..v.len()).collect();
+    indices.par_sort_by(|a, b| total_cmp(&v[*a], &v[*b]));
+    indices
+}
+
+// The element that would be at position `n` if `v` were sorted (quickselect).
+// Each round partitions the remaining candidates around a pivot in parallel.
+pub fn nth_element<T: FloatVector>(v: &[T], n: usize) -> Option<T>
+{
+    if n >= v.len() {
+        return None;
+    }
+
+    let mut candidates = v.to_vec();
+    let mut n = n;
+    loop {
+        let pivot = median_of_three(&candidates);
+
+        let (less, rest): (Vec<T>, Vec<T>) = candidates.par_iter()
+            .partition(|e| total_cmp(*e, &pivot) == Ordering::Less);
+
+        if n < less.len() {
+            candidates = less;
+            continue;
+        }
+        n -= less.len();
+
+        let (equal, greater): (Vec<T>, Vec<T>) = rest.par_iter()
+            .partition(|e| total_cmp(*e, &pivot) == Ordering::Equal);
+
+        if n < equal.len() {
+            return Some(pivot);
+        }
+        n -= equal.len();
+        candidates = greater;
+    }
+}
+
+fn median_of_three<T: FloatVector>(v: &[T]) -> T
+{
+    let mut samples = [v[0], v[v.len() / 2], v[v.len() - 1]];
+    samples.sort_unstable_by(total_cmp);
+    samples[1]
+}
+
+// The `k` largest elements with their indices, largest first.
+// Ties are broken in favour of the lower index; NaN counts as the largest value.
+pub fn top_k<T: FloatVector>(v: &[T], k: usize) -> Vec<(usize, T)>
+{
+    let descending = |a: &(usize, T), b: &(usize, T)| {
+        total_cmp(&b.1, &a.1).then(a.0.cmp(&b.0))
+    };
+
+    let truncate = |mut acc: Vec<(usize, T)>| {
+        if acc.len() > k {
+            acc.select_nth_unstable_by(k, descending);
+            acc.truncate(k);
+        }
+        acc
+    };
+
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut top = v.par_iter()
+        .enumerate()
+        .fold(Vec::new, |mut acc, (i, e)| {
+            acc.push((i, *e));
+            if acc.len() >= 2 * k { truncate(acc) } else { acc }
+        })
+        .map(truncate)
+        .reduce(Vec::new, |mut a, b| {
+            a.extend(b);
+            truncate(a)
+        });
+
+    top.sort_unstable_by(descending);
+    top
+}
+
+// Stable partition: elements satisfying `predicate` are moved to the front.
+// Returns the number of such elements.
+pub fn partition<T, P>(v: &mut [T], predicate: P) -> usize where
+    T: FloatVector,
+    P: Fn(&T) -> bool + Sync + Send,
+{
+    let (front, back): (Vec<T>, Vec<T>) = v.par_iter().partition(|e| predicate(e));
+    let split = front.len();
+    v[..split].copy_from_slice(&front);
+    v[split..].copy_from_slice(&back);
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorting() {
+        let mut a = vec![3.0, f64::NAN, -1.0, 0.0, -0.0, f64::NEG_INFINITY, 2.0];
+        let indices = argsort(&a);
+        sort(&mut a);
+
+        assert_eq!(indices, vec![5, 2, 4, 3, 6, 0, 1]);
+        assert_eq!(&a[..6], &[f64::NEG_INFINITY, -1.0, -0.0, 0.0, 2.0, 3.0]);
+        assert!(a[3].is_sign_positive() && a[2].is_sign_negative());
+        assert!(a[6].is_nan());
+    }
+
+    #[test]
+    fn selection() {
+        let a: Vec<f32> = (0..1000).map(|i| ((i * 7919) % 1000) as f32).collect();
+
+        assert_eq!(nth_element(&a, 0), Some(0.0));
+        assert_eq!(nth_element(&a, 500), Some(500.0));
+        assert_eq!(nth_element(&a, 999), Some(999.0));
+        assert_eq!(nth_element(&a, 1000), None);
+
+        let top = top_k(&a, 3);
+        assert_eq!(top.iter().map(|(_, e)| *e).collect::<Vec<_>>(), vec![999.0, 998.0, 997.0]);
+        for (i, e) in top {
+            assert_eq!(a[i], e);
+        }
+
+        let mut b = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let split = partition(&mut b, |e| *e % 2.0 == 0.0);
+        assert_eq!(split, 3);
+        assert_eq!(b, vec![2.0, 4.0, 6.0, 1.0, 3.0, 5.0]);
+    }
+}
@@ -0,0 +1,125 @@
+// Property-based checks of the element-wise kernels against sequential reference
+// implementations, for both f32 and f64.
+
+use parallel::ShapeError;
+use proptest::prelude::*;
+
+macro_rules! properties {
+    ($name:ident, $t:ty) => {
+        mod $name {
+            use super::*;
+
+            fn values() -> impl Strategy<Value = Vec<$t>> {
+                prop::collection::vec(-1e3 as $t..1e3 as $t, 0..512)
+            }
+
+            fn pairs() -> impl Strategy<Value = (Vec<$t>, Vec<$t>)> {
+                (0usize..512).prop_flat_map(|n| (
+                    prop::collection::vec(-1e3 as $t..1e3 as $t, n),
+                    prop::collection::vec(-1e3 as $t..1e3 as $t, n)))
+            }
+
+            fn nonzero() -> impl Strategy<Value = $t> {
+                prop_oneof![-1e3 as $t..-1e-3 as $t, 1e-3 as $t..1e3 as $t]
+            }
+
+            fn close(a: &[$t], b: &[$t]) -> bool {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| {
+                    (a - b).abs() <= 1e3 * <$t>::EPSILON * a.abs().max(b.abs()).max(1.0)
+                })
+            }
+
+            proptest! {
+                #[test]
+                fn scalar_matches_sequential(v in values(), s in nonzero()) {
+                    let mut a = v.clone();
+                    parallel::sc_add(&mut a, s);
+                    prop_assert_eq!(a, v.iter().map(|e| e + s).collect::<Vec<_>>());
+
+                    let mut a = v.clone();
+                    parallel::sc_sub(&mut a, s);
+                    prop_assert_eq!(a, v.iter().map(|e| e - s).collect::<Vec<_>>());
+
+                    let mut a = v.clone();
+                    parallel::sc_mul(&mut a, s);
+                    prop_assert_eq!(a, v.iter().map(|e| e * s).collect::<Vec<_>>());
+
+                    let mut a = v.clone();
+                    parallel::sc_div(&mut a, s);
+                    prop_assert_eq!(a, v.iter().map(|e| e / s).collect::<Vec<_>>());
+
+                    let mut a = v.clone();
+                    parallel::set(&mut a, s);
+                    prop_assert!(a.iter().all(|e| *e == s));
+
+                    parallel::default(&mut a);
+                    prop_assert!(a.iter().all(|e| *e == 0.0));
+                }
+
+                #[test]
+                fn vector_matches_sequential((a, b) in pairs()) {
+                    let mut c = a.clone();
+                    parallel::vc_add(&mut c, &b).unwrap();
+                    prop_assert_eq!(c, a.iter().zip(&b).map(|(a, b)| a + b).collect::<Vec<_>>());
+
+                    let mut c = a.clone();
+                    parallel::vc_sub(&mut c, &b).unwrap();
+                    prop_assert_eq!(c, a.iter().zip(&b).map(|(a, b)| a - b).collect::<Vec<_>>());
+
+                    let mut c = a.clone();
+                    parallel::vc_mul(&mut c, &b).unwrap();
+                    prop_assert_eq!(c, a.iter().zip(&b).map(|(a, b)| a * b).collect::<Vec<_>>());
+
+                    let mut c = a.clone();
+                    parallel::vc_div(&mut c, &b).unwrap();
+                    let expected: Vec<$t> = a.iter().zip(&b).map(|(a, b)| a / b).collect();
+                    prop_assert!(c.iter().zip(&expected).all(|(c, e)| c == e || (c.is_nan() && e.is_nan())));
+
+                    prop_assert!(parallel::equal(&a, &a.clone()));
+                }
+
+                #[test]
+                fn round_trip((a, b) in pairs(), s in nonzero()) {
+                    let mut c = a.clone();
+                    parallel::sc_add(&mut c, s);
+                    parallel::sc_sub(&mut c, s);
+                    prop_assert!(close(&c, &a));
+
+                    let mut c = a.clone();
+                    parallel::sc_mul(&mut c, s);
+                    parallel::sc_div(&mut c, s);
+                    prop_assert!(close(&c, &a));
+
+                    let mut c = a.clone();
+                    parallel::vc_add(&mut c, &b).unwrap();
+                    parallel::vc_sub(&mut c, &b).unwrap();
+                    prop_assert!(close(&c, &a));
+
+                    let b: Vec<$t> = b.iter().map(|e| if e.abs() < 1e-3 { 1.0 } else { *e }).collect();
+                    let mut c = a.clone();
+                    parallel::vc_mul(&mut c, &b).unwrap();
+                    parallel::vc_div(&mut c, &b).unwrap();
+                    prop_assert!(close(&c, &a));
+                }
+
+                #[test]
+                fn shape_mismatch(a in values(), b in values()) {
+                    prop_assume!(a.len() != b.len());
+                    let expected = Err(ShapeError{ expected: a.len(), found: b.len() });
+
+                    let mut c = a.clone();
+                    prop_assert_eq!(parallel::vc_add(&mut c, &b), expected);
+                    prop_assert_eq!(parallel::vc_sub(&mut c, &b), expected);
+                    prop_assert_eq!(parallel::vc_mul(&mut c, &b), expected);
+                    prop_assert_eq!(parallel::vc_div(&mut c, &b), expected);
+                    prop_assert_eq!(&c, &a);
+
+                    prop_assert!(!parallel::equal(&a, &b));
+                }
+            }
+        }
+    };
+}
+
+properties!(f32_kernels, f32);
+properties!(f64_kernels, f64);
@@ -1,211 +1,965 @@
-pub mod symmetric {
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use compression::{Algorithm, Compression};
+use futures::Stream;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
+use tokio::net::{tcp, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::{unix, UnixStream};
+use tokio_util::codec::{Decoder, Encoder};
 
-    use futures::prelude::*;
-    use serde::{Deserialize, Serialize};
-    use std::net::Ipv4Addr;
-    use tokio::net::{TcpListener, TcpStream};
-    use tokio::net::tcp::{ReadHalf, WriteHalf};
-    use tokio_serde::SymmetricallyFramed;
-    use tokio_serde::formats::*;
-    use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
-
-    #[derive(Debug)]
-    pub enum Error<'a, T> where
-        T: for<'de> Deserialize<'de> + Serialize,
-    {
-        IO(std::io::Error),
-        ReadError(<Reader<'a, T> as futures::TryStream>::Error),
-        WriteError(<Writer<'a, T> as futures::Sink<T>>::Error),
-    }
+pub mod compression;
+pub mod format;
+pub mod local;
+pub mod tls;
 
-    pub type Reader<'a, T> = SymmetricallyFramed<
-        FramedRead<ReadHalf<'a>, LengthDelimitedCodec>,
-        T,
-        SymmetricalBincode<T>>;
+// Errors from both kinds of channel. They don't depend on the message type, so they can be
+// boxed, stored and converted into application errors with `?`.
+#[derive(Debug)]
+pub enum Error {
+    // The socket failed: connecting, accepting, reading or writing.
+    Io(io::Error),
+    // The byte stream couldn't be split into frames, e.g. a length prefix beyond the limit
+    // or a connection closed in the middle of a frame.
+    Framing(io::Error),
+    // A frame arrived intact, but doesn't hold a valid message.
+    Decode(format::BoxError),
+    // A message couldn't be serialized.
+    Encode(format::BoxError),
+    // Format negotiation found no format that both peers support.
+    NoCommonFormat,
+    // Nothing, not even a heartbeat, arrived within the keepalive timeout.
+    PeerUnresponsive(Duration),
+    // A frame, sent or received, is longer than the frame layout allows.
+    FrameTooLarge { length: usize, max: usize },
+    // The TLS configuration is invalid, or the handshake failed.
+    Tls(io::Error),
+}
 
-    pub type Writer<'a, T> = SymmetricallyFramed<
-        FramedWrite<WriteHalf<'a>, LengthDelimitedCodec>,
-        T,
-        SymmetricalBincode<T>>;
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Framing(e) => write!(f, "framing error: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+            Error::Encode(e) => write!(f, "failed to encode message: {}", e),
+            Error::NoCommonFormat => f.write_str("peers have no wire format in common"),
+            Error::PeerUnresponsive(t) => write!(f, "peer unresponsive: nothing received for {:?}", t),
+            Error::FrameTooLarge{ length, max } => write!(f, "frame of {} bytes exceeds the limit of {} bytes", length, max),
+            Error::Tls(e) => write!(f, "TLS error: {}", e),
+        }
+    }
+}
 
-    pub struct Receiver<'a, T> {
-        reader: Reader<'a, T>,
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Framing(e) | Error::Tls(e) => Some(e),
+            Error::Decode(e) | Error::Encode(e) => Some(e.as_ref()),
+            Error::NoCommonFormat | Error::PeerUnresponsive(_) | Error::FrameTooLarge{ .. } => None,
+        }
     }
+}
 
-    impl<'a, T> Receiver<'a, T> where
-        T: for<'de> Deserialize<'de> + Serialize,
-        Reader<'a, T> : TryStream<Ok=T> + Unpin,
-    {
-        pub async fn recv(&mut self) -> Result<Option<T>, Error<'a, T>> {
-            Ok(self.reader.try_next().await.map_err(Error::ReadError)?)
+// `FramedRead` and `FramedWrite` report failures of the socket itself through this.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+// Heartbeats keep idle connections alive and let each side notice a peer that has gone
+// away without closing the connection, which TCP alone only reports after a very long
+// time (if ever). A channel with keepalive sends a heartbeat whenever it hasn't sent
+// anything for `interval`, and gives up with `Error::PeerUnresponsive` when it hasn't
+// received anything for `timeout`.
+//
+// Both peers must enable keepalive, or compression: either adds a kind byte to every frame
+// to tell heartbeats and compressed messages from plain messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Message(Bytes),
+    Heartbeat,
+}
+
+const MESSAGE: u8 = 0;
+const HEARTBEAT: u8 = 1;
+
+// How frames are laid out on the wire: `length_field_offset` header bytes (written as
+// zeros, ignored when reading), the payload length in a `length_field_length`-byte
+// unsigned integer, then the payload. The default matches `LengthDelimitedCodec`'s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameLayout {
+    pub max_frame_length: usize,
+    // 1 to 8 bytes.
+    pub length_field_length: usize,
+    pub length_field_offset: usize,
+    pub big_endian: bool,
+}
+
+impl Default for FrameLayout {
+    fn default() -> Self {
+        FrameLayout{
+            max_frame_length: 8 * 1024 * 1024,
+            length_field_length: 4,
+            length_field_offset: 0,
+            big_endian: true,
         }
     }
+}
 
-    pub struct Sender<'a, T> {
-        writer: Writer<'a, T>,
+impl FrameLayout {
+    // The limit of the configuration, or of what the length field can hold if that's lower.
+    // Either way, a frame's total length, header included, fits in a `usize`.
+    fn max(&self) -> usize {
+        let field_max = match self.length_field_length {
+            8 => u64::MAX,
+            n => (1 << (8 * n)) - 1,
+        };
+        let total_max = usize::MAX - self.header_length();
+        (self.max_frame_length as u64).min(field_max).min(total_max as u64) as usize
     }
 
-    impl<'a, T> Sender<'a, T> where
-        T: for<'de> Deserialize<'de> + Serialize,
-        Writer<'a, T> : Sink<T> + Unpin
-    {
-        pub async fn send(&mut self, item: T) -> Result<(), Error<'a, T>> {
-            Ok(self.writer.send(item).await.map_err(Error::WriteError)?)
+    // Panics unless the length field is 1 to 8 bytes long.
+    fn checked(self) -> Self {
+        assert!((1..=8).contains(&self.length_field_length), "length field must be 1 to 8 bytes long");
+        self
+    }
+
+    fn header_length(&self) -> usize {
+        self.length_field_offset + self.length_field_length
+    }
+}
+
+// Length-delimited framing. Whatever doesn't fit the frame layout is a framing error, as
+// opposed to an I/O error on the socket underneath. Tagged frames start with a kind byte.
+#[derive(Clone, Debug, Default)]
+pub struct Framing {
+    layout: FrameLayout,
+    tagged: bool,
+    compression: Option<Compression>,
+    stats: Arc<compression::Stats>,
+}
+
+impl Framing {
+    // Panics unless the length field is 1 to 8 bytes long.
+    pub fn new(layout: FrameLayout, tagged: bool) -> Self {
+        Framing{ layout: layout.checked(), tagged, ..Framing::default() }
+    }
+
+    // Compression needs tagged frames.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Framing{ tagged: true, compression: Some(compression), ..self }
+    }
+
+    pub fn stats(&self) -> &Arc<compression::Stats> {
+        &self.stats
+    }
+
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, Error> {
+        let header_length = self.layout.header_length();
+        if src.len() < header_length {
+            return Ok(None);
         }
+        let mut field = &src[self.layout.length_field_offset..header_length];
+        let length = if self.layout.big_endian {
+            field.get_uint(self.layout.length_field_length)
+        } else {
+            field.get_uint_le(self.layout.length_field_length)
+        };
+        let max = self.layout.max();
+        if length > max as u64 {
+            return Err(Error::FrameTooLarge{ length: length.min(usize::MAX as u64) as usize, max });
+        }
+        let length = length as usize;
+        if src.len() < header_length + length {
+            src.reserve(header_length + length - src.len());
+            return Ok(None);
+        }
+        src.advance(header_length);
+        Ok(Some(src.split_to(length)))
     }
+}
+
+impl Decoder for Framing {
+    type Item = Frame;
+    type Error = Error;
 
-    pub struct Channel<T> {
-        socket: TcpStream,
-        ghost: std::marker::PhantomData<T>,
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        let mut frame = match self.decode_frame(src)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        if !self.tagged {
+            self.stats.record_received(frame.len(), frame.len(), false);
+            return Ok(Some(Frame::Message(frame.freeze())));
+        }
+        match frame.first().copied() {
+            Some(MESSAGE) => {
+                let payload = frame.split_off(1).freeze();
+                self.stats.record_received(payload.len(), payload.len(), false);
+                Ok(Some(Frame::Message(payload)))
+            }
+            Some(HEARTBEAT) => Ok(Some(Frame::Heartbeat)),
+            Some(kind) => match Algorithm::from_kind(kind) {
+                Some(algorithm) => {
+                    let payload = algorithm.decompress(&frame[1..], self.layout.max())?;
+                    self.stats.record_received(payload.len(), frame.len() - 1, true);
+                    Ok(Some(Frame::Message(payload.into())))
+                }
+                None => Err(Error::Framing(io::Error::new(io::ErrorKind::InvalidData, "unknown frame kind"))),
+            },
+            None => Err(Error::Framing(io::Error::new(io::ErrorKind::InvalidData, "empty frame"))),
+        }
     }
 
-    impl<T> Channel<T> where
-        T: for<'de> Deserialize<'de> + Serialize,
-    {
-        pub async fn connect(address: &Ipv4Addr, port: u16)
-            -> Result<Channel<T>, Error<'_, T>>
-        {
-            let address = format!("{}:{}", address, port);
-            let socket = TcpStream::connect(&address).await.map_err(Error::IO)?;
-            Ok(Channel{ socket, ghost: Default::default() })
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        match self.decode(src)? {
+            None if !src.is_empty() => Err(Error::Framing(
+                io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in the middle of a frame"))),
+            frame => Ok(frame),
         }
+    }
+}
 
-        pub async fn accept(address: &Ipv4Addr, port: u16)
-            -> Result<Channel<T>, Error<'_, T>>
-        {
-            let address = format!("{}:{}", address, port);
-            let mut listener = TcpListener::bind(&address).await.map_err(Error::IO)?;
-            let (socket, _) = listener.accept().await.map_err(Error::IO)?;
-            Ok(Channel{ socket, ghost: Default::default() })
+impl Encoder for Framing {
+    type Item = Frame;
+    type Error = Error;
+
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        let max = self.layout.max();
+        // The peer couldn't decompress a message beyond the limit either.
+        if let Frame::Message(payload) = &item {
+            if payload.len() > max {
+                return Err(Error::FrameTooLarge{ length: payload.len(), max });
+            }
+        }
+        // The serialized and the framed size of a message, and whether it was compressed.
+        let (frame, message) = match (self.tagged, item) {
+            (false, Frame::Message(payload)) => {
+                let length = payload.len();
+                (payload, Some((length, length, false)))
+            }
+            (false, Frame::Heartbeat) => return Err(Error::Framing(
+                io::Error::new(io::ErrorKind::InvalidInput, "heartbeats need tagged frames"))),
+            (true, Frame::Message(payload)) => {
+                let compressed = self.compression
+                    .filter(|c| payload.len() >= c.threshold)
+                    .map(|c| (c.algorithm.kind(), c.algorithm.compress(&payload)))
+                    .filter(|(_, compressed)| compressed.len() < payload.len());
+                let (kind, body) = match &compressed {
+                    Some((kind, compressed)) => (*kind, &compressed[..]),
+                    None => (MESSAGE, &payload[..]),
+                };
+                let mut frame = BytesMut::with_capacity(body.len() + 1);
+                frame.put_u8(kind);
+                frame.put_slice(body);
+                (frame.freeze(), Some((payload.len(), body.len(), compressed.is_some())))
+            }
+            (true, Frame::Heartbeat) => (Bytes::from_static(&[HEARTBEAT]), None),
+        };
+        if frame.len() > max {
+            return Err(Error::FrameTooLarge{ length: frame.len(), max });
+        }
+        if let Some((payload, wire, compressed)) = message {
+            self.stats.record_sent(payload, wire, compressed);
         }
+        dst.reserve(self.layout.header_length() + frame.len());
+        dst.resize(dst.len() + self.layout.length_field_offset, 0);
+        if self.layout.big_endian {
+            dst.put_uint(frame.len() as u64, self.layout.length_field_length);
+        } else {
+            dst.put_uint_le(frame.len() as u64, self.layout.length_field_length);
+        }
+        dst.put_slice(&frame);
+        Ok(())
+    }
+}
 
-        pub fn split(&mut self)
-            -> (Sender<'_, T>, Receiver<'_, T>)
-        {
-            let (reader, writer) = self.socket.split();
+// A byte stream channels can run over, and how it splits into a read and a write half.
+// TCP and Unix streams split natively. Other streams go through `tokio::io::split`, whose
+// halves take turns at the stream behind a lock; implementing this trait the same way runs
+// channels over streams of your own.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Sized + 'static {
+    type ReadHalf<'a>: AsyncRead + Unpin where Self: 'a;
+    type WriteHalf<'a>: AsyncWrite + Unpin where Self: 'a;
+    // Owned halves may be moved into tasks.
+    type OwnedReadHalf: AsyncRead + Unpin + Send + 'static;
+    type OwnedWriteHalf: AsyncWrite + Unpin + Send + 'static;
+
+    fn split(&mut self) -> (Self::ReadHalf<'_>, Self::WriteHalf<'_>);
+    fn into_split(self) -> (Self::OwnedReadHalf, Self::OwnedWriteHalf);
+    // Hands the halves back unless both were split off the same stream.
+    #[allow(clippy::type_complexity)]
+    fn reunite(reader: Self::OwnedReadHalf, writer: Self::OwnedWriteHalf)
+        -> Result<Self, (Self::OwnedReadHalf, Self::OwnedWriteHalf)>;
+}
+
+impl Transport for TcpStream {
+    type ReadHalf<'a> = tcp::ReadHalf<'a>;
+    type WriteHalf<'a> = tcp::WriteHalf<'a>;
+    type OwnedReadHalf = tcp::OwnedReadHalf;
+    type OwnedWriteHalf = tcp::OwnedWriteHalf;
+
+    fn split(&mut self) -> (tcp::ReadHalf<'_>, tcp::WriteHalf<'_>) {
+        TcpStream::split(self)
+    }
 
-            let reader: FramedRead<
-                ReadHalf,
-                LengthDelimitedCodec,
-            > = FramedRead::new(reader, LengthDelimitedCodec::new());
-            let reader = SymmetricallyFramed::new(reader, SymmetricalBincode::default());
+    fn into_split(self) -> (tcp::OwnedReadHalf, tcp::OwnedWriteHalf) {
+        TcpStream::into_split(self)
+    }
 
-            let writer: FramedWrite<
-                WriteHalf,
-                LengthDelimitedCodec,
-            > = FramedWrite::new(writer, LengthDelimitedCodec::new());
-            let writer = SymmetricallyFramed::new(writer, SymmetricalBincode::default());
+    fn reunite(reader: tcp::OwnedReadHalf, writer: tcp::OwnedWriteHalf)
+        -> Result<Self, (tcp::OwnedReadHalf, tcp::OwnedWriteHalf)>
+    {
+        reader.reunite(writer).map_err(|tcp::ReuniteError(reader, writer)| (reader, writer))
+    }
+}
 
-            (Sender{ writer }, Receiver{ reader })
+#[cfg(unix)]
+impl Transport for UnixStream {
+    type ReadHalf<'a> = unix::ReadHalf<'a>;
+    type WriteHalf<'a> = unix::WriteHalf<'a>;
+    type OwnedReadHalf = unix::OwnedReadHalf;
+    type OwnedWriteHalf = unix::OwnedWriteHalf;
+
+    fn split(&mut self) -> (unix::ReadHalf<'_>, unix::WriteHalf<'_>) {
+        UnixStream::split(self)
+    }
+
+    fn into_split(self) -> (unix::OwnedReadHalf, unix::OwnedWriteHalf) {
+        UnixStream::into_split(self)
+    }
+
+    fn reunite(reader: unix::OwnedReadHalf, writer: unix::OwnedWriteHalf)
+        -> Result<Self, (unix::OwnedReadHalf, unix::OwnedWriteHalf)>
+    {
+        reader.reunite(writer).map_err(|unix::ReuniteError(reader, writer)| (reader, writer))
+    }
+}
+
+macro_rules! split_with_lock {
+    ($($stream:ty),*) => {$(
+        impl Transport for $stream {
+            type ReadHalf<'a> = tokio::io::ReadHalf<&'a mut $stream>;
+            type WriteHalf<'a> = tokio::io::WriteHalf<&'a mut $stream>;
+            type OwnedReadHalf = tokio::io::ReadHalf<$stream>;
+            type OwnedWriteHalf = tokio::io::WriteHalf<$stream>;
+
+            fn split(&mut self) -> (Self::ReadHalf<'_>, Self::WriteHalf<'_>) {
+                tokio::io::split(self)
+            }
+
+            fn into_split(self) -> (Self::OwnedReadHalf, Self::OwnedWriteHalf) {
+                tokio::io::split(self)
+            }
+
+            fn reunite(reader: Self::OwnedReadHalf, writer: Self::OwnedWriteHalf)
+                -> Result<Self, (Self::OwnedReadHalf, Self::OwnedWriteHalf)>
+            {
+                if reader.is_pair_of(&writer) {
+                    Ok(reader.unsplit(writer))
+                } else {
+                    Err((reader, writer))
+                }
+            }
         }
+    )*};
+}
+
+split_with_lock!(DuplexStream, tls::ClientStream, tls::ServerStream);
+
+// Stays bound and yields a channel, and the peer's address, for every incoming connection.
+// Use `symmetric::ChannelListener` or `asymmetric::ChannelListener` to pick the channel type.
+pub struct ChannelListener<C> {
+    listener: TcpListener,
+    ghost: PhantomData<fn() -> C>,
+}
+
+impl<C: From<TcpStream>> ChannelListener<C> {
+    pub async fn bind<A: ToSocketAddrs>(address: A) -> Result<ChannelListener<C>, Error> {
+        let listener = TcpListener::bind(address).await.map_err(Error::Io)?;
+        Ok(ChannelListener::from(listener))
+    }
+
+    // With port 0, this is where the port the OS picked can be found.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr().map_err(Error::Io)
+    }
+
+    pub async fn accept(&mut self) -> Result<(C, SocketAddr), Error> {
+        let (socket, peer) = self.listener.accept().await.map_err(Error::Io)?;
+        Ok((C::from(socket), peer))
     }
 }
 
+impl<C> From<TcpListener> for ChannelListener<C> {
+    fn from(listener: TcpListener) -> Self {
+        ChannelListener{ listener, ghost: PhantomData }
+    }
+}
+
+// Never ends: a failed accept is yielded as an error and the listener keeps going.
+impl<C: From<TcpStream>> Stream for ChannelListener<C> {
+    type Item = Result<(C, SocketAddr), Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().listener.poll_accept(cx)
+            .map_ok(|(socket, peer)| (C::from(socket), peer))
+            .map_err(Error::Io)
+            .map(Some)
+    }
+}
+
+// A channel that sends and receives the same type is an asymmetric channel with `I = O`.
+pub mod symmetric {
+
+    use super::asymmetric;
+    use super::format::Bincode;
+    use tokio::net::TcpStream;
+
+    pub use super::asymmetric::{OwnedReader, OwnedWriter, Reader, Writer};
+
+    pub type Receiver<'a, T, F = Bincode, S = TcpStream> = asymmetric::Receiver<'a, T, T, F, S>;
+    pub type Sender<'a, T, F = Bincode, S = TcpStream> = asymmetric::Sender<'a, T, T, F, S>;
+    pub type OwnedReceiver<T, F = Bincode, S = TcpStream> = asymmetric::OwnedReceiver<T, T, F, S>;
+    pub type OwnedSender<T, F = Bincode, S = TcpStream> = asymmetric::OwnedSender<T, T, F, S>;
+    pub type ReuniteError<T, F = Bincode, S = TcpStream> = asymmetric::ReuniteError<T, T, F, S>;
+
+    pub type Channel<T, F = Bincode, S = TcpStream> = asymmetric::Channel<T, T, F, S>;
+    pub type Builder<T, F = Bincode> = asymmetric::Builder<T, T, F>;
+    pub type ChannelListener<T, F = Bincode> = super::ChannelListener<Channel<T, F>>;
+}
+
 pub mod asymmetric {
 
+    use bytes::Bytes;
     use futures::prelude::*;
     use serde::{Deserialize, Serialize};
-    use std::net::Ipv4Addr;
-    use tokio::net::{TcpListener, TcpStream};
-    use tokio::net::tcp::{ReadHalf, WriteHalf};
-    use tokio_serde::Framed;
-    use tokio_serde::formats::*;
-    use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
-
-    #[derive(Debug)]
-    pub enum Error<'a, I, O> where
+    use std::marker::PhantomData;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Weak};
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+    use tokio::sync::Mutex;
+    use tokio::time::Instant;
+    use tokio_util::codec::{FramedRead, FramedWrite};
+    use super::{Error, Frame, FrameLayout, Framing, Keepalive, Transport};
+    use super::compression::{Compression, Stats};
+    use super::format::{self, Bincode, Format, Wire};
+
+    // Channels run over a `TcpStream` unless stated otherwise, but any `Transport` will do:
+    // see `Builder::wrap`.
+    pub type Reader<'a, S = TcpStream> = FramedRead<<S as Transport>::ReadHalf<'a>, Framing>;
+    pub type Writer<'a, S = TcpStream> = FramedWrite<<S as Transport>::WriteHalf<'a>, Framing>;
+    pub type OwnedReader<S = TcpStream> = FramedRead<<S as Transport>::OwnedReadHalf, Framing>;
+    pub type OwnedWriter<S = TcpStream> = FramedWrite<<S as Transport>::OwnedWriteHalf, Framing>;
+
+    // Reads frames until a message arrives, skipping heartbeats.
+    async fn receive<R>(reader: &mut R, timeout: Option<Duration>) -> Result<Option<Bytes>, Error> where
+        R: Stream<Item = Result<Frame, Error>> + Unpin,
+    {
+        loop {
+            let frame = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, reader.next()).await
+                    .map_err(|_| Error::PeerUnresponsive(timeout))?,
+                None => reader.next().await,
+            };
+            match frame.transpose()? {
+                Some(Frame::Message(payload)) => return Ok(Some(payload)),
+                Some(Frame::Heartbeat) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn decode<O, F>(format: &F, payload: Option<Bytes>) -> Result<Option<O>, Error> where
+        O: for<'de> Deserialize<'de>,
+        F: Format,
+    {
+        payload.map(|payload| format.deserialize(&payload)).transpose().map_err(Error::Decode)
+    }
+
+    fn encode<I: Serialize, F: Format>(format: &F, item: &I) -> Result<Frame, Error> {
+        format.serialize(item).map(|payload| Frame::Message(payload.into())).map_err(Error::Encode)
+    }
+
+    pub struct Receiver<'a, I, O, F = Bincode, S: Transport + 'a = TcpStream> {
+        reader: Reader<'a, S>,
+        format: F,
+        keepalive: Option<Keepalive>,
+        // Shared with the sender, for the heartbeats.
+        outgoing: Arc<Mutex<Outgoing<Writer<'a, S>>>>,
+        ghost: PhantomData<fn(I) -> O>,
+    }
+
+    impl<'a, I, O, F, S> Receiver<'a, I, O, F, S> where
         I: for<'de> Deserialize<'de> + Serialize,
         O: for<'de> Deserialize<'de> + Serialize,
+        F: Format,
+        S: Transport,
     {
-        IO(std::io::Error),
-        ReadError(<Reader<'a, I, O> as futures::TryStream>::Error),
-        WriteError(<Writer<'a, I, O> as futures::Sink<I>>::Error),
+        // With keepalive, sends heartbeats while waiting: halves borrowed from the channel
+        // can't leave that to a task of their own. A heartbeat that was still being written
+        // when a message arrived stays buffered, and is flushed first thing on the next call
+        // (or by the next `send`).
+        pub async fn recv(&mut self) -> Result<Option<O>, Error> {
+            let payload = match self.keepalive {
+                Some(keepalive) => {
+                    let outgoing = Arc::downgrade(&self.outgoing);
+                    let beating = async move {
+                        if let Some(pending) = outgoing.upgrade() {
+                            if pending.lock().await.flush().await.is_err() {
+                                return;
+                            }
+                        }
+                        heartbeat(outgoing, keepalive.interval).await
+                    }.then(|()| future::pending::<()>());
+                    tokio::select! {
+                        payload = receive(&mut self.reader, Some(keepalive.timeout)) => payload?,
+                        _ = beating => unreachable!("heartbeats end in a pending future"),
+                    }
+                }
+                None => receive(&mut self.reader, None).await?,
+            };
+            decode(&self.format, payload)
+        }
+    }
+
+    pub struct Sender<'a, I, O, F = Bincode, S: Transport + 'a = TcpStream> {
+        outgoing: Arc<Mutex<Outgoing<Writer<'a, S>>>>,
+        format: F,
+        ghost: PhantomData<fn(I) -> O>,
     }
 
-    pub type Reader<'a, I, O> = Framed<
-        FramedRead<ReadHalf<'a>, LengthDelimitedCodec>,
-        O,
-        I,
-        Bincode<O, I>>;
+    impl<'a, I, O, F, S> Sender<'a, I, O, F, S> where
+        I: for<'de> Deserialize<'de> + Serialize,
+        O: for<'de> Deserialize<'de> + Serialize,
+        F: Format,
+        S: Transport,
+    {
+        pub async fn send(&mut self, item: I) -> Result<(), Error> {
+            let frame = encode(&self.format, &item)?;
+            self.outgoing.lock().await.send(frame).await
+        }
+    }
 
-    pub type Writer<'a, I, O> = Framed<
-        FramedWrite<WriteHalf<'a>, LengthDelimitedCodec>,
-        O,
-        I,
-        Bincode<O, I>>;
+    // Owned halves can be moved into separate tasks and outlive the scope that split the
+    // channel. With keepalive, a task of their own sends the heartbeats, so unlike the
+    // halves of `split` they keep the connection alive while nobody is receiving.
 
-    pub struct Receiver<'a, I, O> {
-        reader: Reader<'a, I, O>,
+    pub struct OwnedReceiver<I, O, F = Bincode, S: Transport = TcpStream> {
+        reader: OwnedReader<S>,
+        format: F,
+        keepalive: Option<Keepalive>,
+        ghost: PhantomData<fn(I) -> O>,
     }
 
-    impl<'a, I, O> Receiver<'a, I, O> where
+    impl<I, O, F, S> OwnedReceiver<I, O, F, S> where
         I: for<'de> Deserialize<'de> + Serialize,
         O: for<'de> Deserialize<'de> + Serialize,
-        Reader<'a, I, O> : TryStream<Ok=O> + Unpin,
+        F: Format,
+        S: Transport,
     {
-        pub async fn recv(&mut self) -> Result<Option<O>, Error<'a, I, O>> {
-            Ok(self.reader.try_next().await.map_err(Error::ReadError)?)
+        pub async fn recv(&mut self) -> Result<Option<O>, Error> {
+            let payload = receive(&mut self.reader, self.keepalive.map(|k| k.timeout)).await?;
+            decode(&self.format, payload)
         }
     }
 
-    pub struct Sender<'a, I, O> {
-        writer: Writer<'a, I, O>,
+    impl<I, O, F, S> OwnedReceiver<I, O, F, S> where
+        F: Clone,
+        S: Transport,
+    {
+        // Puts the halves produced by `Channel::into_split` back together. Fails, handing
+        // both halves back, if they belong to different channels, if the receiver has
+        // buffered bytes of a frame it hasn't returned yet, or if the sender has bytes it
+        // hasn't written yet (because a send was cancelled or failed); they would be lost
+        // otherwise.
+        // The halves are handed back by value, as `tokio::net::tcp::ReuniteError` does.
+        #[allow(clippy::result_large_err, clippy::type_complexity)]
+        pub fn reunite(self, sender: OwnedSender<I, O, F, S>) -> Result<Channel<I, O, F, S>, ReuniteError<I, O, F, S>> {
+            let unflushed = match sender.outgoing.try_lock() {
+                Ok(outgoing) => outgoing.unflushed,
+                Err(_) => true,
+            };
+            if unflushed || !self.reader.read_buffer().is_empty() {
+                return Err(ReuniteError(sender, self));
+            }
+            // Fails only while the heartbeat task is sending.
+            let outgoing = match Arc::try_unwrap(sender.outgoing) {
+                Ok(outgoing) => outgoing.into_inner(),
+                Err(outgoing) => return Err(ReuniteError(OwnedSender{ outgoing, ..sender }, self)),
+            };
+            let (format, keepalive, framing) = (self.format, self.keepalive, self.reader.decoder().clone());
+            let reader = self.reader.into_inner();
+            let writer = outgoing.writer.into_inner();
+            match S::reunite(reader, writer) {
+                Ok(socket) => Ok(Channel{ socket, format, keepalive, framing, ghost: Default::default() }),
+                Err((reader, writer)) => {
+                    let (sender, receiver) = owned_halves(reader, writer, format, keepalive, framing);
+                    Err(ReuniteError(sender, receiver))
+                }
+            }
+        }
     }
 
-    impl<'a, I, O> Sender<'a, I, O> where
+    struct Outgoing<W> {
+        writer: W,
+        last_sent: Instant,
+        // The writer may hold bytes it hasn't written: the last send or flush didn't finish.
+        unflushed: bool,
+    }
+
+    impl<W: Sink<Frame, Error = Error> + Unpin> Outgoing<W> {
+        fn new(writer: W) -> Self {
+            Outgoing{ writer, last_sent: Instant::now(), unflushed: false }
+        }
+
+        async fn send(&mut self, frame: Frame) -> Result<(), Error> {
+            self.unflushed = true;
+            self.writer.send(frame).await?;
+            self.unflushed = false;
+            self.last_sent = Instant::now();
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), Error> {
+            self.writer.flush().await?;
+            self.unflushed = false;
+            Ok(())
+        }
+    }
+
+    pub struct OwnedSender<I, O, F = Bincode, S: Transport = TcpStream> {
+        outgoing: Arc<Mutex<Outgoing<OwnedWriter<S>>>>,
+        format: F,
+        ghost: PhantomData<fn(I) -> O>,
+    }
+
+    impl<I, O, F, S> OwnedSender<I, O, F, S> where
         I: for<'de> Deserialize<'de> + Serialize,
         O: for<'de> Deserialize<'de> + Serialize,
-        Writer<'a, I, O> : Sink<I> + Unpin
+        F: Format,
+        S: Transport,
+    {
+        pub async fn send(&mut self, item: I) -> Result<(), Error> {
+            let frame = encode(&self.format, &item)?;
+            self.outgoing.lock().await.send(frame).await
+        }
+    }
+
+    // Sends a heartbeat whenever nothing has been sent for `interval`, until the sender is
+    // dropped or the connection fails. Holds on to the sender only while sending.
+    async fn heartbeat<W>(outgoing: Weak<Mutex<Outgoing<W>>>, interval: Duration) where
+        W: Sink<Frame, Error = Error> + Unpin,
+    {
+        loop {
+            let due = match outgoing.upgrade() {
+                Some(outgoing) => outgoing.lock().await.last_sent + interval,
+                None => return,
+            };
+            tokio::time::delay_until(due).await;
+
+            let outgoing = match outgoing.upgrade() {
+                Some(outgoing) => outgoing,
+                None => return,
+            };
+            let mut outgoing = outgoing.lock().await;
+            if outgoing.last_sent + interval <= Instant::now() && outgoing.send(Frame::Heartbeat).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    pub struct ReuniteError<I, O, F = Bincode, S: Transport = TcpStream>(pub OwnedSender<I, O, F, S>, pub OwnedReceiver<I, O, F, S>);
+
+    impl<I, O, F, S: Transport> std::fmt::Debug for ReuniteError<I, O, F, S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("ReuniteError(..)")
+        }
+    }
+
+    impl<I, O, F, S: Transport> std::fmt::Display for ReuniteError<I, O, F, S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("tried to reunite halves that are not from the same channel, or with unread data")
+        }
+    }
+
+    impl<I, O, F, S: Transport> std::error::Error for ReuniteError<I, O, F, S> {}
+
+    // Heartbeats need tagged frames too.
+    fn tagged(framing: Framing, keepalive: Option<Keepalive>) -> Framing {
+        Framing{ tagged: framing.tagged || keepalive.is_some(), ..framing }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn owned_halves<I, O, F: Clone, S: Transport>(
+        reader: S::OwnedReadHalf, writer: S::OwnedWriteHalf, format: F, keepalive: Option<Keepalive>, framing: Framing)
+        -> (OwnedSender<I, O, F, S>, OwnedReceiver<I, O, F, S>)
     {
-        pub async fn send(&mut self, item: I) -> Result<(), Error<'a, I, O>> {
-            Ok(self.writer.send(item).await.map_err(Error::WriteError)?)
+        let framing = tagged(framing, keepalive);
+        let reader = FramedRead::new(reader, framing.clone());
+        let writer = FramedWrite::new(writer, framing);
+
+        let outgoing = Arc::new(Mutex::new(Outgoing::new(writer)));
+        if let Some(keepalive) = keepalive {
+            tokio::spawn(heartbeat(Arc::downgrade(&outgoing), keepalive.interval));
         }
+
+        (OwnedSender{ outgoing, format: format.clone(), ghost: PhantomData },
+         OwnedReceiver{ reader, format, keepalive, ghost: PhantomData })
     }
 
-    pub struct Channel<I, O> {
-        socket: TcpStream,
+    // Sends `I` and receives `O`, encoded with the format `F`, over the byte stream `S`.
+    pub struct Channel<I, O, F = Bincode, S = TcpStream> {
+        socket: S,
+        format: F,
+        keepalive: Option<Keepalive>,
+        // Cloned for each half, so all halves share the compression stats.
+        framing: Framing,
         ghost: std::marker::PhantomData<(I, O)>,
     }
 
-    impl<'a, I, O> Channel<I, O> where
+    pub type ChannelListener<I, O, F = Bincode> = super::ChannelListener<Channel<I, O, F>>;
+
+    impl<I, O, F: Default> From<TcpStream> for Channel<I, O, F> {
+        fn from(socket: TcpStream) -> Self {
+            Builder::default().wrap(socket)
+        }
+    }
+
+    impl<I, O> Channel<I, O> {
+        pub fn builder() -> Builder<I, O> {
+            Builder::default()
+        }
+    }
+
+    // Configures a channel before connecting or accepting, for example
+    // `Channel::builder().format(Json).max_frame_length(64 * 1024).connect(address)`.
+    pub struct Builder<I, O, F = Bincode> {
+        format: F,
+        keepalive: Option<Keepalive>,
+        framing: Framing,
+        ghost: PhantomData<fn(I) -> O>,
+    }
+
+    impl<I, O, F: Default> Default for Builder<I, O, F> {
+        fn default() -> Self {
+            Builder{ format: F::default(), keepalive: None, framing: Framing::default(), ghost: PhantomData }
+        }
+    }
+
+    impl<I, O, F> Builder<I, O, F> {
+        pub fn format<G>(self, format: G) -> Builder<I, O, G> {
+            Builder{ format, keepalive: self.keepalive, framing: self.framing, ghost: PhantomData }
+        }
+
+        pub fn keepalive(self, keepalive: Keepalive) -> Self {
+            Builder{ keepalive: Some(keepalive), ..self }
+        }
+
+        pub fn compression(mut self, compression: Compression) -> Self {
+            self.framing = self.framing.with_compression(compression);
+            self
+        }
+
+        pub fn frame_layout(mut self, layout: FrameLayout) -> Self {
+            self.framing.layout = layout.checked();
+            self
+        }
+
+        pub fn max_frame_length(mut self, length: usize) -> Self {
+            self.framing.layout.max_frame_length = length;
+            self
+        }
+
+        // Panics unless `length` is 1 to 8 bytes.
+        pub fn length_field_length(mut self, length: usize) -> Self {
+            self.framing.layout.length_field_length = length;
+            self.framing.layout = self.framing.layout.checked();
+            self
+        }
+
+        pub fn length_field_offset(mut self, offset: usize) -> Self {
+            self.framing.layout.length_field_offset = offset;
+            self
+        }
+
+        pub fn big_endian(mut self) -> Self {
+            self.framing.layout.big_endian = true;
+            self
+        }
+
+        pub fn little_endian(mut self) -> Self {
+            self.framing.layout.big_endian = false;
+            self
+        }
+
+        // The same configuration for the other end of the channel, with stats of its own.
+        pub(super) fn reversed(&self) -> Builder<O, I, F> where
+            F: Clone,
+        {
+            let framing = Framing{ stats: Default::default(), ..self.framing.clone() };
+            Builder{ format: self.format.clone(), keepalive: self.keepalive, framing, ghost: PhantomData }
+        }
+
+        // Runs the channel over a stream that is already connected.
+        pub fn wrap<S>(self, socket: S) -> Channel<I, O, F, S> {
+            Channel{ socket, format: self.format, keepalive: self.keepalive, framing: self.framing, ghost: Default::default() }
+        }
+
+        pub async fn connect<A: ToSocketAddrs>(self, address: A) -> Result<Channel<I, O, F>, Error> {
+            let socket = TcpStream::connect(address).await.map_err(Error::Io)?;
+            Ok(self.wrap(socket))
+        }
+
+        pub async fn accept_from(self, listener: &mut TcpListener) -> Result<Channel<I, O, F>, Error> {
+            let (socket, _) = listener.accept().await.map_err(Error::Io)?;
+            Ok(self.wrap(socket))
+        }
+    }
+
+    impl<I, O, F> Channel<I, O, F> where
         I: for<'de> Deserialize<'de> + Serialize,
         O: for<'de> Deserialize<'de> + Serialize,
+        F: Format + Clone + Default,
     {
-        pub async fn connect(address: &Ipv4Addr, port: u16)
-            -> Result<Channel<I, O>, Error<'a, I, O>>
+        // `address` is anything tokio can resolve: a `SocketAddr`, an `(ip, port)` pair, or a
+        // string such as "localhost:8080" or "[::1]:8080".
+        pub async fn connect<A: ToSocketAddrs>(address: A)
+            -> Result<Channel<I, O, F>, Error>
         {
-            let address = format!("{}:{}", address, port);
-            let socket = TcpStream::connect(&address).await.map_err(Error::IO)?;
-            Ok(Channel{ socket, ghost: Default::default() })
+            let socket = TcpStream::connect(address).await.map_err(Error::Io)?;
+            Ok(Channel::from(socket))
         }
 
-        pub async fn accept(address: &Ipv4Addr, port: u16)
-            -> Result<Channel<I, O>, Error<'a, I, O>>
+        // Binds `address`, accepts a single connection and closes the listener again.
+        pub async fn accept<A: ToSocketAddrs>(address: A)
+            -> Result<Channel<I, O, F>, Error>
         {
-            let address = format!("{}:{}", address, port);
-            let mut listener = TcpListener::bind(&address).await.map_err(Error::IO)?;
-            let (socket, _) = listener.accept().await.map_err(Error::IO)?;
-            Ok(Channel{ socket, ghost: Default::default() })
+            let mut listener = TcpListener::bind(address).await.map_err(Error::Io)?;
+            Self::accept_from(&mut listener).await
+        }
+
+        // Accepts a connection on a listener the caller has bound already. Binding to port 0
+        // and asking the listener for its `local_addr` gives a free port to connect to.
+        pub async fn accept_from(listener: &mut TcpListener)
+            -> Result<Channel<I, O, F>, Error>
+        {
+            let (socket, _) = listener.accept().await.map_err(Error::Io)?;
+            Ok(Channel::from(socket))
+        }
+    }
+
+    impl<I, O, F> Channel<I, O, F> {
+        pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+            self.socket.local_addr().map_err(Error::Io)
+        }
+
+        pub fn peer_addr(&self) -> Result<SocketAddr, Error> {
+            self.socket.peer_addr().map_err(Error::Io)
+        }
+    }
+
+    impl<I, O, F, S> Channel<I, O, F, S> where
+        I: for<'de> Deserialize<'de> + Serialize,
+        O: for<'de> Deserialize<'de> + Serialize,
+        F: Format + Clone,
+        S: Transport,
+    {
+        // Declares the format both peers have agreed on out of band.
+        pub fn with_format<G: Format + Clone>(self, format: G) -> Channel<I, O, G, S> {
+            Channel{ socket: self.socket, format, keepalive: self.keepalive, framing: self.framing, ghost: Default::default() }
+        }
+
+        // See `Keepalive`. The halves of `split` send heartbeats while the receiver waits in
+        // `recv`, those of `into_split` from a task of their own, so `into_split` must be
+        // called from within a Tokio runtime.
+        pub fn with_keepalive(self, keepalive: Keepalive) -> Self {
+            Channel{ keepalive: Some(keepalive), ..self }
+        }
+
+        // Both peers must use the same layout. Panics unless the length field is 1 to 8 bytes
+        // long.
+        pub fn with_frame_layout(mut self, layout: FrameLayout) -> Self {
+            self.framing.layout = layout.checked();
+            self
+        }
+
+        // See `compression`. Messages from the peer are decompressed whether or not this is
+        // called, as long as frames are tagged.
+        pub fn with_compression(self, compression: Compression) -> Self {
+            Channel{ framing: self.framing.with_compression(compression), ..self }
+        }
+
+        // Counts what this channel, and every half split off it, sent and received.
+        pub fn compression_stats(&self) -> Arc<Stats> {
+            self.framing.stats().clone()
         }
 
+        pub fn format(&self) -> &F {
+            &self.format
+        }
+
+        // The stream underneath, e.g. to look at a TLS session.
+        pub fn get_ref(&self) -> &S {
+            &self.socket
+        }
+
+        #[allow(clippy::type_complexity)]
         pub fn split(&mut self)
-            -> (Sender<'_, I, O>, Receiver<'_, I, O>)
+            -> (Sender<'_, I, O, F, S>, Receiver<'_, I, O, F, S>)
         {
+            let framing = tagged(self.framing.clone(), self.keepalive);
             let (reader, writer) = self.socket.split();
 
-            let reader: FramedRead<
-                ReadHalf,
-                LengthDelimitedCodec,
-            > = FramedRead::new(reader, LengthDelimitedCodec::new());
-            let reader = Framed::new(reader, Bincode::default());
+            let reader = FramedRead::new(reader, framing.clone());
+            let writer = FramedWrite::new(writer, framing);
+            let outgoing = Arc::new(Mutex::new(Outgoing::new(writer)));
+
+            (Sender{ outgoing: outgoing.clone(), format: self.format.clone(), ghost: PhantomData },
+             Receiver{ reader, format: self.format.clone(), keepalive: self.keepalive, outgoing, ghost: PhantomData })
+        }
+
+        #[allow(clippy::type_complexity)]
+        pub fn into_split(self)
+            -> (OwnedSender<I, O, F, S>, OwnedReceiver<I, O, F, S>)
+        {
+            let (reader, writer) = self.socket.into_split();
+            owned_halves(reader, writer, self.format, self.keepalive, self.framing)
+        }
+    }
 
-            let writer: FramedWrite<
-                WriteHalf,
-                LengthDelimitedCodec,
-            > = FramedWrite::new(writer, LengthDelimitedCodec::new());
-            let writer = Framed::new(writer, Bincode::default());
+    // Negotiates the format at connection time, before anything else is sent. The
+    // connecting side proposes the formats it accepts, most preferred first, and the
+    // accepting side chooses the first of them it accepts too.
+    impl<I, O, S> Channel<I, O, Wire, S> where
+        I: for<'de> Deserialize<'de> + Serialize,
+        O: for<'de> Deserialize<'de> + Serialize,
+        S: Transport,
+    {
+        pub async fn propose(mut self, formats: &[Wire]) -> Result<Self, Error> {
+            self.format = format::propose(&mut self.socket, formats).await?;
+            Ok(self)
+        }
 
-            (Sender{ writer }, Receiver{ reader })
+        pub async fn choose(mut self, formats: &[Wire]) -> Result<Self, Error> {
+            self.format = format::choose(&mut self.socket, formats).await?;
+            Ok(self)
         }
     }
 }
@@ -0,0 +1,171 @@
+use super::Error;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Per-message compression.
+//
+// Messages of at least `threshold` bytes are compressed before framing, and each frame
+// says whether, and how, its payload is compressed. Peers may therefore use different
+// algorithms, or none, as long as both tag their frames (see `Keepalive`). A message that
+// doesn't get smaller is sent as it is.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Zstd,
+    Lz4,
+    Deflate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    pub algorithm: Algorithm,
+    // Smaller messages aren't worth compressing.
+    pub threshold: usize,
+}
+
+impl Compression {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Compression{ algorithm, threshold: 256 }
+    }
+}
+
+impl Algorithm {
+    // Frame kinds 2 and up; 0 and 1 are uncompressed messages and heartbeats.
+    pub(crate) fn kind(self) -> u8 {
+        match self {
+            Algorithm::Zstd => 2,
+            Algorithm::Lz4 => 3,
+            Algorithm::Deflate => 4,
+        }
+    }
+
+    pub(crate) fn from_kind(kind: u8) -> Option<Algorithm> {
+        match kind {
+            2 => Some(Algorithm::Zstd),
+            3 => Some(Algorithm::Lz4),
+            4 => Some(Algorithm::Deflate),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn compress(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Zstd => zstd::bulk::compress(payload, 0).expect("compressing to memory can't fail"),
+            Algorithm::Lz4 => lz4_flex::compress_prepend_size(payload),
+            Algorithm::Deflate => {
+                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(payload).expect("compressing to memory can't fail");
+                encoder.finish().expect("compressing to memory can't fail")
+            }
+        }
+    }
+
+    // Fails with `Error::FrameTooLarge` rather than inflating more than `max` bytes.
+    pub(crate) fn decompress(self, compressed: &[u8], max: usize) -> Result<Vec<u8>, Error> {
+        let too_large = |length| Error::FrameTooLarge{ length, max };
+        let invalid = |e| Error::Framing(io::Error::new(io::ErrorKind::InvalidData, e));
+        match self {
+            Algorithm::Zstd => {
+                let mut payload = Vec::new();
+                zstd::stream::read::Decoder::new(compressed)
+                    .and_then(|decoder| decoder.take(max as u64 + 1).read_to_end(&mut payload))
+                    .map_err(|e| invalid(e.to_string()))?;
+                if payload.len() > max {
+                    return Err(too_large(payload.len()));
+                }
+                Ok(payload)
+            }
+            Algorithm::Lz4 => {
+                let length = compressed.get(..4)
+                    .map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize)
+                    .ok_or_else(|| invalid("truncated lz4 frame".to_string()))?;
+                if length > max {
+                    return Err(too_large(length));
+                }
+                lz4_flex::decompress_size_prepended(compressed).map_err(|e| invalid(e.to_string()))
+            }
+            Algorithm::Deflate => {
+                let mut payload = Vec::new();
+                flate2::read::DeflateDecoder::new(compressed).take(max as u64 + 1).read_to_end(&mut payload)
+                    .map_err(|e| invalid(e.to_string()))?;
+                if payload.len() > max {
+                    return Err(too_large(payload.len()));
+                }
+                Ok(payload)
+            }
+        }
+    }
+}
+
+// Totals for one direction of a channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub messages: u64,
+    pub compressed_messages: u64,
+    // Serialized size of the messages.
+    pub payload_bytes: u64,
+    // Size of the same messages after compression, without frame headers.
+    pub wire_bytes: u64,
+}
+
+impl Totals {
+    // Payload bytes per byte on the wire; 1.0 while nothing has been sent.
+    pub fn ratio(&self) -> f64 {
+        if self.wire_bytes == 0 {
+            1.0
+        } else {
+            self.payload_bytes as f64 / self.wire_bytes as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    messages: AtomicU64,
+    compressed_messages: AtomicU64,
+    payload_bytes: AtomicU64,
+    wire_bytes: AtomicU64,
+}
+
+impl Counters {
+    fn add(&self, payload_bytes: usize, wire_bytes: usize, compressed: bool) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.compressed_messages.fetch_add(compressed as u64, Ordering::Relaxed);
+        self.payload_bytes.fetch_add(payload_bytes as u64, Ordering::Relaxed);
+        self.wire_bytes.fetch_add(wire_bytes as u64, Ordering::Relaxed);
+    }
+
+    fn totals(&self) -> Totals {
+        Totals{
+            messages: self.messages.load(Ordering::Relaxed),
+            compressed_messages: self.compressed_messages.load(Ordering::Relaxed),
+            payload_bytes: self.payload_bytes.load(Ordering::Relaxed),
+            wire_bytes: self.wire_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Shared by a channel and all halves split off it.
+#[derive(Debug, Default)]
+pub struct Stats {
+    sent: Counters,
+    received: Counters,
+}
+
+impl Stats {
+    pub fn sent(&self) -> Totals {
+        self.sent.totals()
+    }
+
+    pub fn received(&self) -> Totals {
+        self.received.totals()
+    }
+
+    pub(crate) fn record_sent(&self, payload_bytes: usize, wire_bytes: usize, compressed: bool) {
+        self.sent.add(payload_bytes, wire_bytes, compressed);
+    }
+
+    pub(crate) fn record_received(&self, payload_bytes: usize, wire_bytes: usize, compressed: bool) {
+        self.received.add(payload_bytes, wire_bytes, compressed);
+    }
+}
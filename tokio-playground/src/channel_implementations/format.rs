@@ -0,0 +1,157 @@
+use super::Error;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Wire formats: how a message is turned into the payload of a frame and back.
+//
+// A format is either fixed by the channel's type (`Bincode`, `Json`, `MessagePack`, `Cbor`
+// or one of your own) or picked at run time with `Wire`, which can also be negotiated with
+// the peer when the connection is set up.
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// Implement this to plug in a codec of your own.
+pub trait Format {
+    fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError>;
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bincode;
+
+impl Format for Bincode {
+    fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError> {
+        Ok(bincode::serialize(item)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Json;
+
+impl Format for Json {
+    fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError> {
+        Ok(serde_json::to_vec(item)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+// Structs are written as maps keyed by field name, so peers written in other languages can
+// read them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MessagePack;
+
+impl Format for MessagePack {
+    fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError> {
+        Ok(rmp_serde::to_vec_named(item)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cbor;
+
+impl Format for Cbor {
+    fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(item, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(ciborium::de::from_reader(bytes)?)
+    }
+}
+
+// One of the built-in formats, chosen at run time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Wire {
+    #[default]
+    Bincode,
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Wire {
+    // The byte that identifies the format during negotiation. 0 means "none".
+    pub fn id(self) -> u8 {
+        match self {
+            Wire::Bincode => 1,
+            Wire::Json => 2,
+            Wire::MessagePack => 3,
+            Wire::Cbor => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Wire> {
+        match id {
+            1 => Some(Wire::Bincode),
+            2 => Some(Wire::Json),
+            3 => Some(Wire::MessagePack),
+            4 => Some(Wire::Cbor),
+            _ => None,
+        }
+    }
+}
+
+impl Format for Wire {
+    fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError> {
+        match self {
+            Wire::Bincode => Bincode.serialize(item),
+            Wire::Json => Json.serialize(item),
+            Wire::MessagePack => MessagePack.serialize(item),
+            Wire::Cbor => Cbor.serialize(item),
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        match self {
+            Wire::Bincode => Bincode.deserialize(bytes),
+            Wire::Json => Json.deserialize(bytes),
+            Wire::MessagePack => MessagePack.deserialize(bytes),
+            Wire::Cbor => Cbor.deserialize(bytes),
+        }
+    }
+}
+
+// Negotiation happens before any frame is sent. The connecting side sends the ids of the
+// formats it accepts, most preferred first, prefixed by their count; the accepting side
+// answers with the id of the first one it supports as well, or 0 if there is none.
+
+pub(crate) async fn propose<S>(socket: &mut S, formats: &[Wire]) -> Result<Wire, Error> where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let formats = &formats[..formats.len().min(u8::MAX as usize)];
+    let mut offer = vec![formats.len() as u8];
+    offer.extend(formats.iter().map(|f| f.id()));
+    socket.write_all(&offer).await?;
+
+    let chosen = socket.read_u8().await?;
+    Wire::from_id(chosen)
+        .filter(|f| formats.contains(f))
+        .ok_or(Error::NoCommonFormat)
+}
+
+pub(crate) async fn choose<S>(socket: &mut S, formats: &[Wire]) -> Result<Wire, Error> where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut offer = vec![0; socket.read_u8().await? as usize];
+    socket.read_exact(&mut offer).await?;
+
+    let chosen = offer.iter()
+        .filter_map(|id| Wire::from_id(*id))
+        .find(|f| formats.contains(f));
+    socket.write_u8(chosen.map(Wire::id).unwrap_or(0)).await?;
+    chosen.ok_or(Error::NoCommonFormat)
+}
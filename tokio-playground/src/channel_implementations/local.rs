@@ -0,0 +1,67 @@
+use super::Error;
+use super::asymmetric::{Builder, Channel};
+use super::format::{Bincode, Format};
+use serde::{Deserialize, Serialize};
+use tokio::io::DuplexStream;
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+// Channels between processes on the same machine, over Unix domain sockets, and within a
+// process, over in-memory pipes. Formats, keepalive, compression and frame layouts work
+// just as they do over TCP, so in-memory pairs make for tests that don't need the network.
+
+#[cfg(unix)]
+pub type UnixChannel<I, O, F = Bincode> = Channel<I, O, F, UnixStream>;
+pub type PipeChannel<I, O, F = Bincode> = Channel<I, O, F, DuplexStream>;
+
+// Bytes each direction of an in-memory pipe buffers before writers wait.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+impl<I, O, F> Builder<I, O, F> {
+    #[cfg(unix)]
+    pub async fn connect_unix<P: AsRef<Path>>(self, path: P) -> Result<UnixChannel<I, O, F>, Error> {
+        let socket = UnixStream::connect(path).await.map_err(Error::Io)?;
+        Ok(self.wrap(socket))
+    }
+
+    #[cfg(unix)]
+    pub async fn accept_unix_from(self, listener: &mut UnixListener) -> Result<UnixChannel<I, O, F>, Error> {
+        let (socket, _) = listener.accept().await.map_err(Error::Io)?;
+        Ok(self.wrap(socket))
+    }
+
+    // Both ends of an in-memory connection, configured alike.
+    pub fn pair(self) -> (PipeChannel<I, O, F>, PipeChannel<O, I, F>) where
+        F: Clone,
+    {
+        let (near, far) = tokio::io::duplex(PIPE_CAPACITY);
+        let other = self.reversed();
+        (self.wrap(near), other.wrap(far))
+    }
+}
+
+#[cfg(unix)]
+impl<I, O, F> UnixChannel<I, O, F> where
+    I: for<'de> Deserialize<'de> + Serialize,
+    O: for<'de> Deserialize<'de> + Serialize,
+    F: Format + Clone + Default,
+{
+    pub async fn connect_unix<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Builder::default().connect_unix(path).await
+    }
+
+    pub async fn accept_unix_from(listener: &mut UnixListener) -> Result<Self, Error> {
+        Builder::default().accept_unix_from(listener).await
+    }
+}
+
+impl<I, O> PipeChannel<I, O> where
+    I: for<'de> Deserialize<'de> + Serialize,
+    O: for<'de> Deserialize<'de> + Serialize,
+{
+    pub fn pair() -> (Self, PipeChannel<O, I>) {
+        Builder::default().pair()
+    }
+}
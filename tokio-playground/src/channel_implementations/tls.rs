@@ -0,0 +1,112 @@
+use super::Error;
+use super::asymmetric::{Builder, Channel};
+use super::format::Format;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientConfig, NoClientAuth, PrivateKey, RootCertStore, ServerConfig,
+};
+use tokio_rustls::webpki::DNSNameRef;
+
+// Channels over TLS, with rustls.
+//
+// The client verifies that the server's certificate chains up to one of the roots it was
+// given and names the domain it connected to. The server may ask for a client certificate
+// too (mutual TLS), which must chain up to one of its own roots.
+//
+// For anything more specialised, build the rustls configurations yourself (`rustls` is
+// re-exported so the versions match) and turn them into a connector or an acceptor with
+// `TlsConnector::from` or `TlsAcceptor::from`.
+
+pub use tokio_rustls::rustls;
+pub use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+pub type ClientStream = tokio_rustls::client::TlsStream<TcpStream>;
+pub type ServerStream = tokio_rustls::server::TlsStream<TcpStream>;
+
+// A certificate chain, leaf first, and the leaf's private key.
+#[derive(Clone)]
+pub struct Identity {
+    pub chain: Vec<Certificate>,
+    pub key: PrivateKey,
+}
+
+fn invalid<E: std::error::Error + Send + Sync + 'static>(error: E) -> Error {
+    Error::Tls(io::Error::new(io::ErrorKind::InvalidInput, error))
+}
+
+fn root_store(roots: &[Certificate]) -> Result<RootCertStore, Error> {
+    let mut store = RootCertStore::empty();
+    for root in roots {
+        store.add(root).map_err(invalid)?;
+    }
+    Ok(store)
+}
+
+// Trusts servers whose certificates chain up to `roots`, and presents `identity` to
+// servers that ask for a client certificate.
+pub fn connector(roots: &[Certificate], identity: Option<Identity>) -> Result<TlsConnector, Error> {
+    let mut config = ClientConfig::new();
+    config.root_store = root_store(roots)?;
+    if let Some(identity) = identity {
+        config.set_single_client_cert(identity.chain, identity.key).map_err(invalid)?;
+    }
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+// Presents `identity` to clients. With `client_roots`, only accepts clients whose
+// certificates chain up to them.
+pub fn acceptor(identity: Identity, client_roots: Option<&[Certificate]>) -> Result<TlsAcceptor, Error> {
+    let verifier = match client_roots {
+        Some(roots) => AllowAnyAuthenticatedClient::new(root_store(roots)?),
+        None => NoClientAuth::new(),
+    };
+    let mut config = ServerConfig::new(verifier);
+    config.set_single_cert(identity.chain, identity.key).map_err(invalid)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+impl<I, O, F> Builder<I, O, F> {
+    // `domain` is the name the server's certificate must be valid for.
+    pub async fn connect_tls<A: ToSocketAddrs>(self, address: A, domain: &str, connector: &TlsConnector)
+        -> Result<Channel<I, O, F, ClientStream>, Error>
+    {
+        let domain = DNSNameRef::try_from_ascii_str(domain)
+            .map_err(|_| Error::Tls(io::Error::new(io::ErrorKind::InvalidInput, "invalid domain name")))?;
+        let socket = TcpStream::connect(address).await.map_err(Error::Io)?;
+        let socket = connector.connect(domain, socket).await.map_err(Error::Tls)?;
+        Ok(self.wrap(socket))
+    }
+
+    pub async fn accept_tls_from(self, listener: &mut TcpListener, acceptor: &TlsAcceptor)
+        -> Result<Channel<I, O, F, ServerStream>, Error>
+    {
+        let (socket, _) = listener.accept().await.map_err(Error::Io)?;
+        let socket = acceptor.accept(socket).await.map_err(Error::Tls)?;
+        Ok(self.wrap(socket))
+    }
+}
+
+impl<I, O, F> Channel<I, O, F, ClientStream> where
+    I: for<'de> Deserialize<'de> + Serialize,
+    O: for<'de> Deserialize<'de> + Serialize,
+    F: Format + Clone + Default,
+{
+    pub async fn connect_tls<A: ToSocketAddrs>(address: A, domain: &str, connector: &TlsConnector)
+        -> Result<Self, Error>
+    {
+        Builder::default().connect_tls(address, domain, connector).await
+    }
+}
+
+impl<I, O, F> Channel<I, O, F, ServerStream> where
+    I: for<'de> Deserialize<'de> + Serialize,
+    O: for<'de> Deserialize<'de> + Serialize,
+    F: Format + Clone + Default,
+{
+    pub async fn accept_tls_from(listener: &mut TcpListener, acceptor: &TlsAcceptor) -> Result<Self, Error> {
+        Builder::default().accept_tls_from(listener, acceptor).await
+    }
+}
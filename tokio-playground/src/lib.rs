@@ -1,15 +1,25 @@
 pub mod channel_implementations;
+pub mod mux;
+pub mod reconnect;
+pub mod reliable;
+pub mod rpc;
 pub mod select;
 
 #[cfg(test)]
 mod tests {
 
+    // Binds an ephemeral port on localhost, so tests don't clash when run concurrently.
+    async fn listen() -> (tokio::net::TcpListener, std::net::SocketAddr) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .expect("failed to bind listener");
+        let address = listener.local_addr().expect("failed to get local address");
+        (listener, address)
+    }
+
     #[test]
     fn asymmetric_send_recv() {
         use crate::channel_implementations::asymmetric::Channel;
         use serde::{Deserialize, Serialize};
-        use std::net::Ipv4Addr;
-        use std::str::FromStr;
 
         #[derive(Debug, PartialEq, Deserialize, Serialize)]
         pub struct Request(String);
@@ -17,10 +27,10 @@ mod tests {
         pub struct Response(String);
 
         tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
-            let handle_1 = tokio::spawn(async {
-                let address = Ipv4Addr::from_str("127.0.0.1")
-                    .expect("failed to construct address");
-                let mut channel: Channel<Request, Response> = Channel::accept(&address, 20000).await
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let mut channel: Channel<Request, Response> = Channel::accept_from(&mut listener).await
                     .expect("failed to accept connection");
 
                 let (mut sender, mut receiver) = channel.split();
@@ -40,11 +50,10 @@ mod tests {
                 assert_eq!(msg, Some(Response(String::from("654"))));
             });
 
-            let handle_2 = tokio::spawn(async {
-                let address = Ipv4Addr::from_str("127.0.0.1")
-                    .expect("failed to construct address");
-                let mut channel: Channel<Response, Request> = Channel::connect(&address, 20000).await
+            let handle_2 = tokio::spawn(async move {
+                let mut channel: Channel<Response, Request> = Channel::connect(address).await
                     .expect("failed to accept connection");
+                assert_eq!(channel.peer_addr().unwrap(), address);
 
                 let (mut sender, mut receiver) = channel.split();
 
@@ -71,14 +80,12 @@ mod tests {
     #[test]
     fn symmetric_send_recv() {
         use crate::channel_implementations::symmetric::Channel;
-        use std::net::Ipv4Addr;
-        use std::str::FromStr;
 
         tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
-            let handle_1 = tokio::spawn(async {
-                let address = Ipv4Addr::from_str("127.0.0.1")
-                    .expect("failed to construct address");
-                let mut channel: Channel<String> = Channel::accept(&address, 21000).await
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let mut channel: Channel<String> = Channel::accept_from(&mut listener).await
                     .expect("failed to accept connection");
 
                 let (mut sender, mut receiver) = channel.split();
@@ -98,10 +105,8 @@ mod tests {
                 assert_eq!(msg, Some(String::from("654")));
             });
 
-            let handle_2 = tokio::spawn(async {
-                let address = Ipv4Addr::from_str("127.0.0.1")
-                    .expect("failed to construct address");
-                let mut channel: Channel<String> = Channel::connect(&address, 21000).await
+            let handle_2 = tokio::spawn(async move {
+                let mut channel: Channel<String> = Channel::connect(("localhost", address.port())).await
                     .expect("failed to accept connection");
 
                 let (mut sender, mut receiver) = channel.split();
@@ -126,6 +131,946 @@ mod tests {
         });
     }
 
+    #[test]
+    fn owned_halves() {
+        use crate::channel_implementations::symmetric::Channel;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let channel: Channel<u64> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+
+                // Echo every message back, doubled:
+                let (mut sender, mut receiver) = channel.into_split();
+                while let Some(msg) = receiver.recv().await.unwrap() {
+                    sender.send(msg * 2).await.unwrap();
+                }
+            });
+
+            let handle_2 = tokio::spawn(async move {
+                let channel: Channel<u64> = Channel::connect(address).await
+                    .expect("failed to accept connection");
+
+                // The halves outlive this scope, so they can be moved into their own tasks:
+                let (mut sender, mut receiver) = channel.into_split();
+                let writer = tokio::spawn(async move {
+                    for i in 0..10 {
+                        sender.send(i).await.unwrap();
+                    }
+                    sender
+                });
+                let reader = tokio::spawn(async move {
+                    for i in 0..10 {
+                        assert_eq!(receiver.recv().await.unwrap(), Some(i * 2));
+                    }
+                    receiver
+                });
+
+                // Every reply has been read, so the halves can be put back together:
+                let (sender, receiver) = (writer.await.unwrap(), reader.await.unwrap());
+                let mut channel = receiver.reunite(sender).expect("failed to reunite halves");
+                let (mut sender, mut receiver) = channel.split();
+                sender.send(21).await.unwrap();
+                assert_eq!(receiver.recv().await.unwrap(), Some(42));
+            });
+
+            handle_2.await.unwrap();
+            handle_1.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn channel_listener() {
+        use crate::channel_implementations::symmetric::{Channel, ChannelListener};
+        use futures::prelude::*;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let mut listener: ChannelListener<String> = ChannelListener::bind("127.0.0.1:0").await
+                .expect("failed to bind listener");
+            let address = listener.local_addr().unwrap();
+
+            // Serve every client from its own task, greeting it with its own address:
+            tokio::spawn(async move {
+                while let Some(accepted) = listener.next().await {
+                    let (channel, peer) = accepted.expect("failed to accept connection");
+                    tokio::spawn(async move {
+                        let (mut sender, mut receiver) = channel.into_split();
+                        while let Some(msg) = receiver.recv().await.unwrap() {
+                            sender.send(format!("{} from {}", msg, peer)).await.unwrap();
+                        }
+                    });
+                }
+            });
+
+            // Connections are open at the same time, and each gets its own replies:
+            let clients = (0..3).map(|i| async move {
+                let mut channel: Channel<String> = Channel::connect(address).await
+                    .expect("failed to connect");
+                let local = channel.local_addr().unwrap();
+                let (mut sender, mut receiver) = channel.split();
+                for j in 0..3 {
+                    sender.send(format!("{}.{}", i, j)).await.unwrap();
+                    let msg = receiver.recv().await.unwrap();
+                    assert_eq!(msg, Some(format!("{}.{} from {}", i, j, local)));
+                }
+            });
+            future::join_all(clients).await;
+        });
+    }
+
+    #[test]
+    fn formats() {
+        use crate::channel_implementations::format::*;
+        use serde::{Deserialize, Serialize};
+        use serde::de::DeserializeOwned;
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Message {
+            id: u32,
+            text: String,
+            values: Vec<f64>,
+        }
+
+        // A user-supplied format:
+        #[derive(Clone, Default)]
+        struct PrettyJson;
+
+        impl Format for PrettyJson {
+            fn serialize<T: Serialize>(&self, item: &T) -> Result<Vec<u8>, BoxError> {
+                Ok(serde_json::to_vec_pretty(item)?)
+            }
+
+            fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxError> {
+                Ok(serde_json::from_slice(bytes)?)
+            }
+        }
+
+        let message = Message{ id: 7, text: String::from("hello"), values: vec![0.5, 1.5] };
+        for wire in &[Wire::Bincode, Wire::Json, Wire::MessagePack, Wire::Cbor] {
+            let bytes = wire.serialize(&message).unwrap();
+            assert_eq!(wire.deserialize::<Message>(&bytes).unwrap(), message);
+            assert_eq!(Wire::from_id(wire.id()), Some(*wire));
+        }
+        assert_eq!(Json.serialize(&message).unwrap(), br#"{"id":7,"text":"hello","values":[0.5,1.5]}"#.to_vec());
+        assert!(Json.deserialize::<Message>(b"{}").is_err());
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            use crate::channel_implementations::symmetric::Channel;
+
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let mut channel: Channel<Message, PrettyJson> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.split();
+                let msg = receiver.recv().await.unwrap().unwrap();
+                sender.send(msg).await.unwrap();
+            });
+
+            // Both formats write JSON, so they interoperate:
+            let channel: Channel<Message> = Channel::connect(address).await
+                .expect("failed to connect");
+            let mut channel = channel.with_format(Json);
+            let (mut sender, mut receiver) = channel.split();
+            sender.send(Message{ id: 1, text: String::from("echo"), values: vec![] }).await.unwrap();
+            assert_eq!(receiver.recv().await.unwrap().unwrap().text, "echo");
+
+            handle_1.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn format_negotiation() {
+        use crate::channel_implementations::Error;
+        use crate::channel_implementations::format::Wire;
+        use crate::channel_implementations::symmetric::{Channel, ChannelListener};
+        use futures::prelude::*;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let mut listener: ChannelListener<String, Wire> = ChannelListener::bind("127.0.0.1:0").await
+                .expect("failed to bind listener");
+            let address = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                while let Some(accepted) = listener.next().await {
+                    let (channel, _) = accepted.expect("failed to accept connection");
+                    let mut channel = match channel.choose(&[Wire::MessagePack, Wire::Json]).await {
+                        Ok(channel) => channel,
+                        Err(error) => {
+                            assert!(matches!(error, Error::NoCommonFormat));
+                            continue;
+                        }
+                    };
+                    let format = *channel.format();
+                    let (mut sender, mut receiver) = channel.split();
+                    while let Some(msg) = receiver.recv().await.unwrap() {
+                        sender.send(format!("{:?}: {}", format, msg)).await.unwrap();
+                    }
+                }
+            });
+
+            // The client's preference wins among the formats both sides support:
+            {
+                let channel: Channel<String, Wire> = Channel::connect(address).await
+                    .expect("failed to connect");
+                let mut channel = channel.propose(&[Wire::Cbor, Wire::Json, Wire::MessagePack]).await.unwrap();
+                assert_eq!(*channel.format(), Wire::Json);
+                let (mut sender, mut receiver) = channel.split();
+                sender.send(String::from("hello")).await.unwrap();
+                assert_eq!(receiver.recv().await.unwrap(), Some(String::from("Json: hello")));
+            }
+
+            let channel: Channel<String, Wire> = Channel::connect(address).await
+                .expect("failed to connect");
+            assert!(matches!(channel.propose(&[Wire::Bincode]).await, Err(Error::NoCommonFormat)));
+        });
+    }
+
+    #[test]
+    fn channel_errors() {
+        use crate::channel_implementations::Error;
+        use crate::channel_implementations::asymmetric::Channel;
+        use tokio::io::AsyncWriteExt;
+
+        fn is_send_sync<E: std::error::Error + Send + Sync + 'static>(_: &E) {}
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let mut channel: Channel<(), bool> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (_, mut receiver) = channel.split();
+
+                // 7 is not a valid `bool`:
+                let error = receiver.recv().await.unwrap_err();
+                is_send_sync(&error);
+                assert!(matches!(error, Error::Decode(_)));
+                assert!(std::error::Error::source(&error).is_some());
+
+                // Errors can be propagated into application error types:
+                let boxed: Box<dyn std::error::Error + Send + Sync> = error.into();
+                assert!(boxed.to_string().starts_with("failed to decode message"));
+
+                let mut channel: Channel<(), bool> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (_, mut receiver) = channel.split();
+
+                // A length prefix beyond the default 8 MiB limit:
+                assert!(matches!(receiver.recv().await, Err(Error::FrameTooLarge{ .. })));
+            });
+
+            let handle_2 = tokio::spawn(async move {
+                let mut channel: Channel<u8, ()> = Channel::connect(address).await
+                    .expect("failed to connect");
+                let (mut sender, _) = channel.split();
+                sender.send(7).await.unwrap();
+
+                let mut socket = tokio::net::TcpStream::connect(address).await
+                    .expect("failed to connect");
+                socket.write_all(&[0xff, 0xff, 0xff, 0xff]).await.unwrap();
+                socket
+            });
+
+            let _socket = handle_2.await.unwrap();
+            handle_1.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn frame_layout() {
+        use crate::channel_implementations::Error;
+        use crate::channel_implementations::symmetric::Channel;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let mut socket = listener.accept().await.expect("failed to accept connection").0;
+                // Two header bytes, then a 2-byte little endian length:
+                let mut frame = [0; 5];
+                socket.read_exact(&mut frame).await.unwrap();
+                assert_eq!(frame, [0, 0, 1, 0, 7]);
+
+                socket.write_all(&[0xff, 0xff, 1, 0, 8]).await.unwrap();
+                socket.write_all(&[0, 0, 0xe8, 0x03]).await.unwrap();
+                socket
+            });
+
+            let handle_2 = tokio::spawn(async move {
+                let mut channel: Channel<u8> = Channel::builder()
+                    .length_field_offset(2)
+                    .length_field_length(2)
+                    .little_endian()
+                    .max_frame_length(64)
+                    .connect(address).await
+                    .expect("failed to connect");
+                let (mut sender, mut receiver) = channel.split();
+
+                sender.send(7).await.unwrap();
+                assert_eq!(receiver.recv().await.unwrap(), Some(8));
+                // A length of 1000:
+                assert!(matches!(receiver.recv().await, Err(Error::FrameTooLarge{ length: 1000, max: 64 })));
+            });
+
+            let _socket = handle_1.await.unwrap();
+            handle_2.await.unwrap();
+
+            let (mut listener, address) = listen().await;
+            let handle = tokio::spawn(async move {
+                Channel::<Vec<u8>>::builder().max_frame_length(64).accept_from(&mut listener).await
+                    .expect("failed to accept connection")
+            });
+            let mut channel: Channel<Vec<u8>> = Channel::connect(address).await.expect("failed to connect");
+            let mut accepted = handle.await.unwrap();
+
+            // Bincode prefixes the bytes with their number as a `u64`:
+            let (mut sender, _) = accepted.split();
+            let error = sender.send(vec![0; 100]).await.unwrap_err();
+            assert!(matches!(error, Error::FrameTooLarge{ length: 108, max: 64 }));
+            assert_eq!(error.to_string(), "frame of 108 bytes exceeds the limit of 64 bytes");
+
+            let (mut sender, _) = channel.split();
+            sender.send(vec![0; 100]).await.unwrap();
+            let (_, mut receiver) = accepted.split();
+            assert!(matches!(receiver.recv().await, Err(Error::FrameTooLarge{ length: 108, max: 64 })));
+
+            // Without a limit of its own, a frame can't be so long that its length overflows:
+            let (mut listener, address) = listen().await;
+            let handle = tokio::spawn(async move {
+                let mut socket = listener.accept().await.expect("failed to accept connection").0;
+                socket.write_all(&[0xff; 8]).await.unwrap();
+                socket
+            });
+            let mut channel: Channel<u8> = Channel::builder()
+                .length_field_length(8)
+                .max_frame_length(usize::MAX)
+                .connect(address).await
+                .expect("failed to connect");
+            let _socket = handle.await.unwrap();
+            let (_, mut receiver) = channel.split();
+            assert!(matches!(receiver.recv().await, Err(Error::FrameTooLarge{ max, .. }) if max == usize::MAX - 8));
+        });
+    }
+
+    #[test]
+    fn compression() {
+        use crate::channel_implementations::Error;
+        use crate::channel_implementations::compression::{Algorithm, Compression};
+        use crate::channel_implementations::symmetric::Channel;
+
+        let big: Vec<u32> = (0..10_000).map(|i| i % 16).collect();
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            for algorithm in &[Algorithm::Zstd, Algorithm::Lz4, Algorithm::Deflate] {
+                let (mut listener, address) = listen().await;
+
+                let server = tokio::spawn({
+                    let big = big.clone();
+                    async move {
+                        // Never compresses, but understands compressed frames:
+                        let channel: Channel<Vec<u32>> = Channel::builder()
+                            .compression(Compression{ algorithm: Algorithm::Lz4, threshold: usize::MAX })
+                            .accept_from(&mut listener).await
+                            .expect("failed to accept connection");
+                        let stats = channel.compression_stats();
+                        let (mut sender, mut receiver) = channel.into_split();
+                        assert_eq!(receiver.recv().await.unwrap(), Some(vec![1, 2, 3]));
+                        assert_eq!(receiver.recv().await.unwrap(), Some(big.clone()));
+                        sender.send(big).await.unwrap();
+
+                        let received = stats.received();
+                        assert_eq!((received.messages, received.compressed_messages), (2, 1));
+                        assert_eq!(stats.sent().compressed_messages, 0);
+                        assert!((stats.sent().ratio() - 1.0).abs() < f64::EPSILON);
+                    }
+                });
+
+                let mut channel: Channel<Vec<u32>> = Channel::connect(address).await
+                    .expect("failed to connect")
+                    .with_compression(Compression::new(*algorithm));
+                let stats = channel.compression_stats();
+                let (mut sender, mut receiver) = channel.split();
+                // Below the threshold:
+                sender.send(vec![1, 2, 3]).await.unwrap();
+                sender.send(big.clone()).await.unwrap();
+                assert_eq!(receiver.recv().await.unwrap(), Some(big.clone()));
+                server.await.unwrap();
+
+                let sent = stats.sent();
+                assert_eq!((sent.messages, sent.compressed_messages), (2, 1));
+                assert!(sent.ratio() > 10.0, "{:?} only compressed by {}", algorithm, sent.ratio());
+                assert_eq!(stats.received().compressed_messages, 0);
+
+                // The frame limit applies to messages before they are compressed:
+                let (mut limited, _peer) = Channel::<Vec<u32>>::builder()
+                    .max_frame_length(1024)
+                    .compression(Compression::new(*algorithm))
+                    .pair();
+                let (mut sender, _) = limited.split();
+                match sender.send(big.clone()).await {
+                    Err(Error::FrameTooLarge{ length, max: 1024 }) => assert!(length > 1024),
+                    other => panic!("expected the message to be too large, got {:?}", other),
+                }
+
+                // Receiving a small message doesn't depend on how large the limit is:
+                let (mut near, mut far) = Channel::<Vec<u32>>::builder()
+                    .length_field_length(8)
+                    .max_frame_length(usize::MAX)
+                    .compression(Compression{ algorithm: *algorithm, threshold: 0 })
+                    .pair();
+                let (mut sender, _) = near.split();
+                sender.send(vec![7; 256]).await.unwrap();
+                let (_, mut receiver) = far.split();
+                assert_eq!(receiver.recv().await.unwrap(), Some(vec![7; 256]));
+            }
+        });
+    }
+
+    #[test]
+    fn tls() {
+        use crate::channel_implementations::Error;
+        use crate::channel_implementations::symmetric::Channel;
+        use crate::channel_implementations::format::Bincode;
+        use crate::channel_implementations::tls::{self, Identity};
+        use crate::channel_implementations::tls::rustls::{Certificate, PrivateKey};
+        use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+
+        // A certificate authority, and identities it issues for localhost.
+        let authority = |key: &KeyPair| {
+            let mut params = CertificateParams::new(Vec::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params.self_signed(key).unwrap()
+        };
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = authority(&ca_key);
+        let roots = vec![Certificate(ca.der().to_vec())];
+        let issue = || {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec!["localhost".to_string()]).unwrap()
+                .signed_by(&key, &ca, &ca_key).unwrap();
+            Identity{ chain: vec![Certificate(cert.der().to_vec())], key: PrivateKey(key.serialize_der()) }
+        };
+        let (server_identity, client_identity) = (issue(), issue());
+
+        // Another authority the server's certificate doesn't chain up to.
+        let other_roots = vec![Certificate(authority(&KeyPair::generate().unwrap()).der().to_vec())];
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+            let acceptor = tls::acceptor(server_identity.clone(), None).unwrap();
+            let mutual = tls::acceptor(server_identity, Some(&roots[..])).unwrap();
+
+            let server = tokio::spawn(async move {
+                let mut channel: Channel<String, Bincode, _> = Channel::accept_tls_from(&mut listener, &acceptor).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.split();
+                let message = receiver.recv().await.unwrap().unwrap();
+                sender.send(message + ", encrypted").await.unwrap();
+
+                // The client doesn't trust the server:
+                assert!(Channel::<String, Bincode, _>::accept_tls_from(&mut listener, &acceptor).await.is_err());
+
+                // A client without a certificate:
+                let result = Channel::<String, Bincode, _>::accept_tls_from(&mut listener, &mutual).await;
+                assert!(matches!(result, Err(Error::Tls(_))));
+
+                let channel: Channel<String, Bincode, _> = Channel::accept_tls_from(&mut listener, &mutual).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.into_split();
+                let message = receiver.recv().await.unwrap().unwrap();
+                sender.send(message + ", mutually").await.unwrap();
+            });
+
+            let connector = tls::connector(&roots, None).unwrap();
+            let mut channel: Channel<String, Bincode, _> = Channel::connect_tls(address, "localhost", &connector).await
+                .expect("failed to connect");
+            let (mut sender, mut receiver) = channel.split();
+            sender.send("hello".to_string()).await.unwrap();
+            assert_eq!(receiver.recv().await.unwrap().unwrap(), "hello, encrypted");
+
+            let untrusting = tls::connector(&other_roots, None).unwrap();
+            let result = Channel::<String, Bincode, _>::connect_tls(address, "localhost", &untrusting).await;
+            assert!(matches!(result, Err(Error::Tls(_))));
+
+            // The server only finds out about the missing certificate after the client has
+            // finished its side of the handshake.
+            if let Ok(mut channel) = Channel::<String, Bincode, _>::connect_tls(address, "localhost", &connector).await {
+                let (_, mut receiver) = channel.split();
+                assert!(receiver.recv().await.is_err());
+            }
+
+            let connector = tls::connector(&roots, Some(client_identity)).unwrap();
+            let mut channel: Channel<String, Bincode, _> = Channel::connect_tls(address, "localhost", &connector).await
+                .expect("failed to connect");
+            let (mut sender, mut receiver) = channel.split();
+            sender.send("hello".to_string()).await.unwrap();
+            assert_eq!(receiver.recv().await.unwrap().unwrap(), "hello, mutually");
+
+            server.await.unwrap();
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix_socket_channels() {
+        use crate::channel_implementations::asymmetric::Channel;
+        use crate::channel_implementations::local::UnixChannel;
+        use tokio::net::UnixListener;
+
+        let directory = tempfile::tempdir().expect("failed to create temporary directory");
+        let path = directory.path().join("channel.sock");
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let mut listener = UnixListener::bind(&path).expect("failed to bind socket");
+            let server = tokio::spawn(async move {
+                let mut channel: UnixChannel<String, u32> = Channel::accept_unix_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.split();
+                let number = receiver.recv().await.unwrap().unwrap();
+                sender.send(number.to_string()).await.unwrap();
+            });
+            let channel: UnixChannel<u32, String> = Channel::connect_unix(&path).await.expect("failed to connect");
+            let (mut sender, mut receiver) = channel.into_split();
+            sender.send(42).await.unwrap();
+            assert_eq!(receiver.recv().await.unwrap().unwrap(), "42");
+            receiver.reunite(sender).expect("failed to reunite halves");
+            server.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn in_memory_channels() {
+        use crate::channel_implementations::asymmetric::Channel;
+        use crate::channel_implementations::compression::{Algorithm, Compression};
+        use crate::channel_implementations::format::Json;
+        use crate::channel_implementations::local::PipeChannel;
+        use crate::rpc::{self, Client};
+        use futures::FutureExt;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            // In memory, with owned halves that reunite:
+            let (near, far): (PipeChannel<u32, String>, _) = Channel::pair();
+            let (mut sender, mut receiver) = near.into_split();
+            let (mut peer_sender, mut peer_receiver) = far.into_split();
+            sender.send(7).await.unwrap();
+            assert_eq!(peer_receiver.recv().await.unwrap(), Some(7));
+            peer_sender.send(String::from("seven")).await.unwrap();
+            assert_eq!(receiver.recv().await.unwrap(), Some(String::from("seven")));
+            let near = receiver.reunite(sender).expect("failed to reunite halves");
+
+            // ...unlike halves of different channels:
+            let (other, _other_peer): (PipeChannel<u32, String>, _) = Channel::pair();
+            let (other_sender, _) = other.into_split();
+            let (_, receiver) = near.into_split();
+            assert!(receiver.reunite(other_sender).is_err());
+
+            // ...or with bytes the sender hasn't written yet:
+            let (near, _far): (PipeChannel<String, ()>, _) = Channel::pair();
+            let (mut sender, receiver) = near.into_split();
+            // More than fits in the pipe, so the send can't finish while nobody reads.
+            assert!(sender.send("tokio ".repeat(20_000)).now_or_never().is_none());
+            assert!(receiver.reunite(sender).is_err());
+
+            // Both ends are configured by the one builder, but keep their own stats:
+            let big = "tokio ".repeat(1000);
+            let (mut near, mut far) = Channel::<String, String>::builder()
+                .format(Json)
+                .compression(Compression::new(Algorithm::Lz4))
+                .pair();
+            near.split().0.send(big.clone()).await.unwrap();
+            assert_eq!(far.split().1.recv().await.unwrap(), Some(big));
+            let (near_stats, far_stats) = (near.compression_stats(), far.compression_stats());
+            assert_eq!((near_stats.sent().compressed_messages, near_stats.received().messages), (1, 0));
+            assert_eq!((far_stats.sent().messages, far_stats.received().compressed_messages), (0, 1));
+
+            // Anything built on channels runs in memory too:
+            let (client, server) = Channel::pair();
+            tokio::spawn(rpc::serve(server, |n: u64| async move { n * 2 }));
+            let client: Client<u64, u64> = Client::new(client);
+            assert_eq!(client.call(21).await, Ok(42));
+        });
+    }
+
+    #[test]
+    fn keepalive() {
+        use crate::channel_implementations::{Error, Keepalive};
+        use crate::channel_implementations::symmetric::Channel;
+        use std::time::Duration;
+
+        // The timeout leaves room for a busy test runner to delay heartbeats.
+        let keepalive = Keepalive{ interval: Duration::from_millis(50), timeout: Duration::from_millis(500) };
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+
+            let handle_1 = tokio::spawn(async move {
+                let channel: Channel<u32> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.with_keepalive(keepalive).into_split();
+
+                // Idle for longer than the timeout, but the peer's heartbeats keep it alive:
+                assert_eq!(receiver.recv().await.unwrap(), Some(1));
+                sender.send(2).await.unwrap();
+
+                // The same with the halves of `split`, which send heartbeats while receiving:
+                let channel: Channel<u32> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let mut channel = channel.with_keepalive(keepalive);
+                let (mut sender, mut receiver) = channel.split();
+                assert_eq!(receiver.recv().await.unwrap(), Some(3));
+                sender.send(4).await.unwrap();
+
+                // A peer that holds the connection open but sends nothing, not even heartbeats:
+                let channel: Channel<u32> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (_sender, mut receiver) = channel.with_keepalive(keepalive).into_split();
+                match receiver.recv().await {
+                    Err(Error::PeerUnresponsive(timeout)) => assert_eq!(timeout, keepalive.timeout),
+                    other => panic!("expected the peer to be unresponsive, got {:?}", other.map(|_| ())),
+                }
+            });
+
+            let handle_2 = tokio::spawn(async move {
+                let channel: Channel<u32> = Channel::connect(address).await
+                    .expect("failed to connect");
+                let (mut sender, mut receiver) = channel.with_keepalive(keepalive).into_split();
+
+                tokio::time::delay_for(keepalive.timeout * 3).await;
+                sender.send(1).await.unwrap();
+                assert_eq!(receiver.recv().await.unwrap(), Some(2));
+
+                let channel: Channel<u32> = Channel::connect(address).await
+                    .expect("failed to connect");
+                let mut channel = channel.with_keepalive(keepalive);
+                let (mut sender, mut receiver) = channel.split();
+                let (received, ()) = futures::join!(receiver.recv(), async {
+                    tokio::time::delay_for(keepalive.timeout * 3).await;
+                    sender.send(3).await.unwrap();
+                });
+                assert_eq!(received.unwrap(), Some(4));
+
+                tokio::net::TcpStream::connect(address).await.expect("failed to connect")
+            });
+
+            let _socket = handle_2.await.unwrap();
+            handle_1.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn rpc() {
+        use crate::channel_implementations::asymmetric::Channel;
+        use crate::rpc::{self, Client};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+            let completed = Arc::new(AtomicUsize::new(0));
+
+            // Sleeps for the requested number of milliseconds, then echoes it back:
+            let counter = completed.clone();
+            let (server, stop) = futures::future::abortable(async move {
+                let channel: Channel<_, _> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                rpc::serve(channel, move |ms: u64| {
+                    let counter = counter.clone();
+                    async move {
+                        tokio::time::delay_for(Duration::from_millis(ms)).await;
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        ms
+                    }
+                }).await
+            });
+            tokio::spawn(server);
+
+            let channel: Channel<_, _> = Channel::connect(address).await.expect("failed to connect");
+            let client: Client<u64, u64> = Client::new(channel);
+
+            // Pipelined calls resolve to their own responses, although those arrive in
+            // reverse order:
+            let calls: Vec<_> = (0..5).map(|i| client.call(250 - 50 * i)).collect();
+            let responses = futures::future::join_all(calls).await;
+            assert_eq!(responses, (0..5).map(|i| Ok(250 - 50 * i)).collect::<Vec<_>>());
+            assert_eq!(completed.load(Ordering::SeqCst), 5);
+
+            // A call that times out is cancelled on the server too:
+            assert_eq!(client.call_timeout(300, Duration::from_millis(50)).await, Err(rpc::Error::Timeout));
+            let call = client.call(300);
+            assert_eq!(client.call(10).await, Ok(10));
+            call.cancel();
+            tokio::time::delay_for(Duration::from_millis(400)).await;
+            assert_eq!(completed.load(Ordering::SeqCst), 6);
+
+            // Outstanding calls fail once the connection goes away:
+            let call = client.call(1000);
+            stop.abort();
+            assert_eq!(call.await, Err(rpc::Error::Closed));
+            assert_eq!(client.call(10).await, Err(rpc::Error::Closed));
+        });
+    }
+
+    #[test]
+    fn mux() {
+        use crate::channel_implementations::local::PipeChannel;
+        use crate::channel_implementations::symmetric::Channel;
+        use crate::mux::{self, Config, Mux, Side, SubChannel};
+        use std::time::Duration;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+            let config = Config{ window: 4 };
+
+            let (accepted, connected): (Result<Channel<_>, _>, Result<Channel<_>, _>) =
+                futures::join!(Channel::accept_from(&mut listener), Channel::connect(address));
+            let mut server = Mux::new(accepted.unwrap(), Side::Acceptor, config);
+            let client = Mux::new(connected.unwrap(), Side::Connector, config);
+
+            // Two streams with different types over the one connection:
+            let mut slow: SubChannel<String, String> = client.open().unwrap();
+            let mut fast: SubChannel<u64, u64> = client.open().unwrap();
+            slow.send(String::from("hello")).await.unwrap();
+            fast.send(1).await.unwrap();
+            let mut slow_peer: SubChannel<String, String> = server.accept().await.unwrap();
+            let mut fast_peer: SubChannel<u64, u64> = server.accept().await.unwrap();
+            assert_eq!((slow.id(), fast.id()), (slow_peer.id(), fast_peer.id()));
+
+            // Nobody reads `slow_peer`, so `slow` runs out of credit after the window...
+            for _ in 1..4 {
+                slow.send(String::from("more")).await.unwrap();
+            }
+            let blocked = tokio::time::timeout(Duration::from_millis(100), slow.send(String::from("blocked")));
+            assert!(blocked.await.is_err());
+
+            // ...while `fast` carries on:
+            assert_eq!(fast_peer.recv().await.unwrap(), Some(1));
+            for i in 2..100 {
+                fast.send(i).await.unwrap();
+                assert_eq!(fast_peer.recv().await.unwrap(), Some(i));
+            }
+
+            // Reading frees up credit again:
+            assert_eq!(slow_peer.recv().await.unwrap(), Some(String::from("hello")));
+            slow.send(String::from("unblocked")).await.unwrap();
+
+            // Closing ends the stream in one direction only:
+            slow.close();
+            for _ in 0..3 {
+                assert_eq!(slow_peer.recv().await.unwrap(), Some(String::from("more")));
+            }
+            assert_eq!(slow_peer.recv().await.unwrap(), Some(String::from("unblocked")));
+            assert_eq!(slow_peer.recv().await.unwrap(), None);
+            slow_peer.send(String::from("reply")).await.unwrap();
+            assert_eq!(slow.recv().await.unwrap(), Some(String::from("reply")));
+            assert!(matches!(slow.send(String::new()).await, Err(mux::Error::Closed)));
+
+            // Resetting ends it in both:
+            fast_peer.reset();
+            assert!(matches!(fast.recv().await, Err(mux::Error::Reset)));
+            assert!(matches!(fast.send(0).await, Err(mux::Error::Reset)));
+
+            // Opening a stream that is open already resets it:
+            let (mut raw, accepted) = PipeChannel::<mux::Frame, mux::Frame>::pair();
+            let mut server = Mux::new(accepted, Side::Acceptor, config);
+            let (mut sender, mut receiver) = raw.split();
+            sender.send(mux::Frame::Open{ stream: 1 }).await.unwrap();
+            let mut stream: SubChannel<u64, u64> = server.accept().await.unwrap();
+            sender.send(mux::Frame::Open{ stream: 1 }).await.unwrap();
+            assert!(matches!(receiver.recv().await.unwrap(), Some(mux::Frame::Reset{ stream: 1 })));
+            assert!(matches!(stream.recv().await, Err(mux::Error::Reset)));
+        });
+    }
+
+    #[test]
+    fn reconnect() {
+        use crate::channel_implementations::symmetric::Channel;
+        use crate::reconnect::{Backoff, Client, Config, Error, State};
+        use std::time::Duration;
+
+        let backoff = Backoff{ initial: Duration::from_millis(10), max: Duration::from_millis(50), ..Backoff::default() };
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+
+            let server = tokio::spawn(async move {
+                let mut channel: Channel<u32> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.split();
+                assert_eq!(receiver.recv().await.unwrap(), Some(1));
+                assert_eq!(receiver.recv().await.unwrap(), Some(2));
+                sender.send(1).await.unwrap();
+                assert_eq!(receiver.recv().await.unwrap(), Some(3));
+                // Restarts, losing 2 and 3.
+                drop(channel);
+
+                let mut channel: Channel<u32> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (_, mut receiver) = channel.split();
+                assert_eq!(receiver.recv().await.unwrap(), Some(2));
+                assert_eq!(receiver.recv().await.unwrap(), Some(3));
+                assert_eq!(receiver.recv().await.unwrap(), Some(4));
+                channel
+            });
+
+            let config: Config = Config{ backoff, resend_buffer: Some(8), ..Config::default() };
+            let mut client: Client<u32, u32> = Client::connect(address, config);
+
+            client.send(1).await.unwrap();
+            client.send(2).await.unwrap();
+            // The server has seen 1:
+            assert_eq!(client.recv().await, Some(1));
+            client.acknowledge(1);
+            client.send(3).await.unwrap();
+
+            client.send(4).await.unwrap();
+            let _channel = server.await.unwrap();
+            assert!(matches!(client.state(), State::Connected{ peer } if peer == address));
+
+            // Nothing listens on this port any more, so the client gives up:
+            let address = listen().await.1;
+            let config: Config = Config{ backoff, max_attempts: Some(3), ..Config::default() };
+            let mut client: Client<u32, u32> = Client::connect(address, config);
+            assert_eq!(client.recv().await, None);
+            assert!(matches!(client.state(), State::Closed));
+            assert_eq!(client.send(1).await, Err(Error::Closed));
+
+            // Nor does it wait for room in a full resend buffer once it has given up:
+            let config: Config = Config{ backoff, max_attempts: Some(3), resend_buffer: Some(2), ..Config::default() };
+            let mut client: Client<u32, u32> = Client::connect(address, config);
+            client.send(1).await.unwrap();
+            client.send(2).await.unwrap();
+            let waiting = tokio::time::timeout(Duration::from_secs(5), client.send(3)).await;
+            assert_eq!(waiting, Ok(Err(Error::Closed)));
+            assert_eq!(client.recv().await, None);
+            assert_eq!(client.send(4).await, Err(Error::Closed));
+        });
+    }
+
+    #[test]
+    fn reliable() {
+        use crate::channel_implementations::asymmetric;
+        use crate::channel_implementations::format::Bincode;
+        use crate::channel_implementations::symmetric::Channel;
+        use crate::reliable::{Error, Packet, Session};
+        use std::time::Duration;
+        use tokio::io::DuplexStream;
+
+        tokio::runtime::Runtime::new().expect("failed to create Tokio runtime").block_on(async {
+            let (mut listener, address) = listen().await;
+
+            let server = tokio::spawn(async move {
+                let mut session: Session<u32, u32> = Session::new(16);
+                let channel: Channel<_> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                session.attach(channel).await.unwrap();
+                assert_eq!(session.recv().await.unwrap(), Some(1));
+                // The connection breaks before 2 and 3 are read.
+                session.detach();
+
+                let channel: Channel<_> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                session.attach(channel).await.unwrap();
+                assert_eq!(session.recv().await.unwrap(), Some(2));
+                assert_eq!(session.recv().await.unwrap(), Some(3));
+                assert_eq!(session.recv().await.unwrap(), Some(4));
+
+                // Duplicates are dropped:
+                let mut channel: Channel<Packet<u32>> = Channel::accept_from(&mut listener).await
+                    .expect("failed to accept connection");
+                let (mut sender, mut receiver) = channel.split();
+                assert!(matches!(receiver.recv().await.unwrap(), Some(Packet::Resume{ delivered: 0 })));
+                sender.send(Packet::Resume{ delivered: 0 }).await.unwrap();
+                for seq in &[1, 1, 2, 1, 3] {
+                    sender.send(Packet::Message{ seq: *seq, body: *seq as u32 * 10 }).await.unwrap();
+                }
+                // Each message is acknowledged once.
+                for seq in 1..=3 {
+                    assert!(matches!(receiver.recv().await.unwrap(), Some(Packet::Ack{ seq: s }) if s == seq));
+                }
+            });
+
+            let mut session: Session<u32, u32> = Session::new(16);
+            let channel: Channel<_> = Channel::connect(address).await.expect("failed to connect");
+            session.attach(channel).await.unwrap();
+            for item in 1..=3 {
+                // Kept even if the server has dropped the connection already.
+                assert_eq!(session.send(item).await.unwrap(), item as u64);
+            }
+            // Sent while detached, so it goes out on the next connection:
+            session.detach();
+            assert_eq!(session.send(4).await.unwrap(), 4);
+            assert_eq!(session.unacknowledged(), 4);
+
+            let channel: Channel<_> = Channel::connect(address).await.expect("failed to connect");
+            session.attach(channel).await.unwrap();
+            // The server acknowledged 1 when resuming, and 2 to 4 when it received them.
+            assert_eq!(session.unacknowledged(), 3);
+
+            let mut session: Session<u32, u32> = Session::new(16);
+            let channel: Channel<_> = Channel::connect(address).await.expect("failed to connect");
+            session.attach(channel).await.unwrap();
+            assert_eq!(session.recv().await.unwrap(), Some(10));
+            assert_eq!(session.recv().await.unwrap(), Some(20));
+            assert_eq!(session.recv().await.unwrap(), Some(30));
+            assert_eq!(session.recv().await.unwrap(), None);
+            assert!(!session.is_attached());
+
+            server.await.unwrap();
+
+            // A peer that never sends anything but acknowledgements still makes room:
+            let mut sender: Session<u32, (), Bincode, DuplexStream> = Session::new(2);
+            let mut receiver: Session<(), u32, Bincode, DuplexStream> = Session::new(2);
+            let (near, far) = asymmetric::Channel::pair();
+            let (attached, peer_attached) = futures::join!(sender.attach(near), receiver.attach(far));
+            attached.unwrap();
+            peer_attached.unwrap();
+            let reading = tokio::spawn(async move {
+                for item in 1..=10 {
+                    assert_eq!(receiver.recv().await.unwrap(), Some(item));
+                }
+                receiver
+            });
+            let sending = async {
+                for item in 1..=10 {
+                    sender.send(item).await.unwrap();
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), sending).await
+                .expect("waited for acknowledgements that had arrived");
+            let mut receiver = reading.await.unwrap();
+
+            // While detached, messages are kept up to the capacity:
+            sender.detach();
+            let mut item = 11;
+            loop {
+                match sender.send(item).await {
+                    Ok(seq) => assert_eq!(seq, item as u64),
+                    Err(Error::Full) => break,
+                    Err(e) => panic!("expected the session to be full, got {:?}", e),
+                }
+                item += 1;
+            }
+            assert_eq!(sender.unacknowledged(), 2);
+
+            // A message that wasn't kept can be sent again without being duplicated:
+            let (near, far) = asymmetric::Channel::pair();
+            let (attached, peer_attached) = futures::join!(sender.attach(near), receiver.attach(far));
+            attached.unwrap();
+            peer_attached.unwrap();
+            assert_eq!(sender.send(item).await.unwrap(), item as u64);
+            for expected in 11..=item {
+                assert_eq!(receiver.recv().await.unwrap(), Some(expected));
+            }
+            assert_eq!(sender.send(0).await.unwrap(), item as u64 + 1);
+            assert_eq!(receiver.recv().await.unwrap(), Some(0));
+        });
+    }
+
     #[test]
     fn channel_comparison() {
 
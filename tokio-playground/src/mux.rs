@@ -0,0 +1,342 @@
+use crate::channel_implementations::Transport;
+use crate::channel_implementations::symmetric::{Channel, OwnedReceiver, OwnedSender};
+use crate::channel_implementations::format::{Bincode, Format};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tokio::sync::{mpsc, Notify};
+
+// Many independently typed sub-channels over a single connection.
+//
+// Each sub-channel ("stream") has an id, chosen by the side that opens it: odd on the
+// connecting side and even on the accepting side, so both can open streams without
+// coordinating. Flow control is per stream and counts messages: a side may have at most
+// `Config::window` messages in flight on a stream before the receiving application has
+// read them, so a slow consumer only holds up its own stream.
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Frame {
+    Open { stream: u32 },
+    Data { stream: u32, payload: Vec<u8> },
+    // The receiver has consumed `messages` more messages, so the sender may send as many.
+    Credit { stream: u32, messages: u32 },
+    // The sender won't send any more; the other direction stays open.
+    Close { stream: u32 },
+    // Abandons the stream in both directions.
+    Reset { stream: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Connector,
+    Acceptor,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    // Messages a stream may have in flight in each direction.
+    pub window: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config{ window: 64 }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // The peer reset the stream.
+    Reset,
+    // The stream was closed for sending, or the connection underneath is gone.
+    Closed,
+    // Every stream id of this side has been used.
+    Exhausted,
+    Encode(crate::channel_implementations::format::BoxError),
+    Decode(crate::channel_implementations::format::BoxError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Reset => f.write_str("stream reset by peer"),
+            Error::Closed => f.write_str("stream closed"),
+            Error::Exhausted => f.write_str("no stream ids left"),
+            Error::Encode(e) => write!(f, "failed to encode message: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Encode(e) | Error::Decode(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// State of one stream shared between its `SubChannel` and the task reading frames.
+struct Stream {
+    credit: AtomicU32,
+    credit_changed: Notify,
+    remote_closed: AtomicBool,
+    reset: AtomicBool,
+}
+
+struct Entry {
+    stream: Arc<Stream>,
+    // `None` once the peer has closed its sending side.
+    inbound: Option<mpsc::UnboundedSender<Vec<u8>>>,
+}
+
+// A stream that has been registered, but not handed to the application yet.
+type Opened = (u32, Arc<Stream>, mpsc::UnboundedReceiver<Vec<u8>>);
+
+struct Shared {
+    config: Config,
+    // `None` once the ids have run out.
+    next_id: Mutex<Option<u32>>,
+    streams: Mutex<HashMap<u32, Entry>>,
+    outgoing: mpsc::UnboundedSender<Frame>,
+    closed: AtomicBool,
+}
+
+impl Shared {
+    fn register(&self, id: u32) -> Opened {
+        let stream = Arc::new(Stream{
+            credit: AtomicU32::new(self.config.window),
+            credit_changed: Notify::new(),
+            remote_closed: AtomicBool::new(false),
+            reset: AtomicBool::new(false),
+        });
+        let (inbound, receiver) = mpsc::unbounded_channel();
+        self.streams.lock().unwrap().insert(id, Entry{ stream: stream.clone(), inbound: Some(inbound) });
+        (id, stream, receiver)
+    }
+
+    fn sub_channel<I, O, F: Format + Default>(self: &Arc<Self>, opened: Opened) -> SubChannel<I, O, F> {
+        let (id, stream, inbound) = opened;
+        SubChannel{
+            id,
+            stream,
+            inbound,
+            shared: self.clone(),
+            format: F::default(),
+            sending: true,
+            ghost: PhantomData,
+        }
+    }
+
+    fn shut_down(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        for (_, entry) in self.streams.lock().unwrap().drain() {
+            entry.stream.credit_changed.notify();
+        }
+    }
+}
+
+pub struct Mux {
+    shared: Arc<Shared>,
+    incoming: mpsc::UnboundedReceiver<Opened>,
+}
+
+impl Mux {
+    // Spawns the tasks that read and write frames on `channel`.
+    pub fn new<S>(channel: Channel<Frame, Bincode, S>, side: Side, config: Config) -> Mux where
+        S: Transport,
+    {
+        let (sender, receiver) = channel.into_split();
+        let (outgoing, queue) = mpsc::unbounded_channel();
+        let (opened, incoming) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared{
+            config,
+            next_id: Mutex::new(Some(if side == Side::Connector { 1 } else { 2 })),
+            streams: Default::default(),
+            outgoing,
+            closed: AtomicBool::new(false),
+        });
+
+        tokio::spawn(write_frames(sender, queue));
+        tokio::spawn(read_frames(receiver, opened, Arc::downgrade(&shared)));
+
+        Mux{ shared, incoming }
+    }
+
+    pub fn open<I, O, F: Format + Default>(&self) -> Result<SubChannel<I, O, F>, Error> {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Err(Error::Closed);
+        }
+        let id = {
+            let mut next_id = self.shared.next_id.lock().unwrap();
+            let id = next_id.ok_or(Error::Exhausted)?;
+            *next_id = id.checked_add(2);
+            id
+        };
+        let sub_channel = self.shared.sub_channel(self.shared.register(id));
+        self.shared.outgoing.send(Frame::Open{ stream: id }).map_err(|_| Error::Closed)?;
+        Ok(sub_channel)
+    }
+
+    // Waits for the peer to open a stream. Both sides must agree on the stream's types.
+    // Returns `None` once the connection is gone.
+    pub async fn accept<I, O, F: Format + Default>(&mut self) -> Option<SubChannel<I, O, F>> {
+        let opened = self.incoming.recv().await?;
+        Some(self.shared.sub_channel(opened))
+    }
+}
+
+async fn write_frames<S>(mut sender: OwnedSender<Frame, Bincode, S>, mut queue: mpsc::UnboundedReceiver<Frame>) where
+    S: Transport,
+{
+    while let Some(frame) = queue.recv().await {
+        if sender.send(frame).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn read_frames<S>(mut receiver: OwnedReceiver<Frame, Bincode, S>, opened: mpsc::UnboundedSender<Opened>, shared: Weak<Shared>) where
+    S: Transport,
+{
+    while let Ok(Some(frame)) = receiver.recv().await {
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        // Registered right away, so data sent straight after opening has somewhere to go
+        // until the stream is accepted. Opening a stream that is open already resets it,
+        // rather than leaving the two sides with different ideas of what it is.
+        if let Frame::Open{ stream } = frame {
+            let live = shared.streams.lock().unwrap().remove(&stream);
+            if let Some(entry) = live {
+                entry.stream.reset.store(true, Ordering::SeqCst);
+                entry.stream.credit_changed.notify();
+                let _ = shared.outgoing.send(Frame::Reset{ stream });
+            } else if opened.send(shared.register(stream)).is_err() {
+                shared.streams.lock().unwrap().remove(&stream);
+                let _ = shared.outgoing.send(Frame::Reset{ stream });
+            }
+            continue;
+        }
+        let mut streams = shared.streams.lock().unwrap();
+        match frame {
+            Frame::Open{ .. } => {}
+            Frame::Data{ stream, payload } => {
+                let delivered = streams.get(&stream)
+                    .and_then(|entry| entry.inbound.as_ref())
+                    .map(|inbound| inbound.send(payload).is_ok())
+                    .unwrap_or(false);
+                if !delivered {
+                    let _ = shared.outgoing.send(Frame::Reset{ stream });
+                }
+            }
+            Frame::Credit{ stream, messages } => {
+                if let Some(entry) = streams.get(&stream) {
+                    entry.stream.credit.fetch_add(messages, Ordering::SeqCst);
+                    entry.stream.credit_changed.notify();
+                }
+            }
+            Frame::Close{ stream } => {
+                if let Some(entry) = streams.get_mut(&stream) {
+                    entry.stream.remote_closed.store(true, Ordering::SeqCst);
+                    entry.inbound = None;
+                }
+            }
+            Frame::Reset{ stream } => {
+                if let Some(entry) = streams.remove(&stream) {
+                    entry.stream.reset.store(true, Ordering::SeqCst);
+                    entry.stream.credit_changed.notify();
+                }
+            }
+        }
+    }
+    if let Some(shared) = shared.upgrade() {
+        shared.shut_down();
+    }
+}
+
+// One stream: sends `I` and receives `O`, encoded with `F`. Dropping it closes the
+// sending side if `close` hasn't already.
+pub struct SubChannel<I, O, F = Bincode> {
+    id: u32,
+    stream: Arc<Stream>,
+    inbound: mpsc::UnboundedReceiver<Vec<u8>>,
+    shared: Arc<Shared>,
+    format: F,
+    sending: bool,
+    ghost: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O, F> SubChannel<I, O, F> where
+    I: Serialize,
+    O: DeserializeOwned,
+    F: Format,
+{
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    // Waits while the peer has `Config::window` unread messages on this stream.
+    pub async fn send(&mut self, item: I) -> Result<(), Error> {
+        let payload = self.format.serialize(&item).map_err(Error::Encode)?;
+        loop {
+            if self.stream.reset.load(Ordering::SeqCst) {
+                return Err(Error::Reset);
+            }
+            if !self.sending || self.shared.closed.load(Ordering::SeqCst) {
+                return Err(Error::Closed);
+            }
+            let taken = self.stream.credit
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |c| c.checked_sub(1))
+                .is_ok();
+            if taken {
+                break;
+            }
+            self.stream.credit_changed.notified().await;
+        }
+        self.shared.outgoing.send(Frame::Data{ stream: self.id, payload }).map_err(|_| Error::Closed)
+    }
+
+    // `Ok(None)` once the peer has closed its sending side.
+    pub async fn recv(&mut self) -> Result<Option<O>, Error> {
+        match self.inbound.recv().await {
+            Some(payload) => {
+                let _ = self.shared.outgoing.send(Frame::Credit{ stream: self.id, messages: 1 });
+                self.format.deserialize(&payload).map(Some).map_err(Error::Decode)
+            }
+            None if self.stream.reset.load(Ordering::SeqCst) => Err(Error::Reset),
+            None if self.stream.remote_closed.load(Ordering::SeqCst) => Ok(None),
+            None => Err(Error::Closed),
+        }
+    }
+}
+
+impl<I, O, F> SubChannel<I, O, F> {
+    // Stops sending; messages from the peer can still be received.
+    pub fn close(&mut self) {
+        if self.sending {
+            self.sending = false;
+            let _ = self.shared.outgoing.send(Frame::Close{ stream: self.id });
+        }
+    }
+
+    // Abandons the stream in both directions.
+    pub fn reset(mut self) {
+        self.sending = false;
+        self.shared.streams.lock().unwrap().remove(&self.id);
+        let _ = self.shared.outgoing.send(Frame::Reset{ stream: self.id });
+    }
+}
+
+impl<I, O, F> Drop for SubChannel<I, O, F> {
+    fn drop(&mut self) {
+        self.close();
+        self.shared.streams.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::channel_implementations::Keepalive;
+use crate::channel_implementations::asymmetric::Channel;
+use crate::channel_implementations::format::{Bincode, Format};
+use futures::future;
+use rand::Rng;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::ToSocketAddrs;
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
+
+// A client channel that reconnects whenever the connection fails, or can't be established
+// in the first place, waiting a little longer before each consecutive attempt.
+//
+// Messages sent while disconnected are queued and go out once the client is connected
+// again. Messages that were already written to a connection that then failed are lost,
+// unless the client keeps a resend buffer: it holds on to every message until the
+// application acknowledges it (typically because the server replied to it), and resends
+// whatever is still in there after reconnecting. The server may therefore see a message
+// twice.
+
+// Exponential backoff: the n-th consecutive attempt waits `initial * multiplier^(n - 1)`,
+// at most `max`, less a random fraction of up to `jitter` of that, so that clients that
+// lost the same server don't all come back at the same moment.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff{
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial.as_secs_f64() * self.multiplier.powi(exponent)).min(self.max.as_secs_f64());
+        let jitter = rand::thread_rng().gen_range(0.0..=self.jitter.clamp(0.0, 1.0));
+        Duration::from_secs_f64(delay * (1.0 - jitter))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config<F = Bincode> {
+    pub backoff: Backoff,
+    // Consecutive failed attempts after which the client gives up. `None` retries forever.
+    pub max_attempts: Option<u32>,
+    // Capacity of the resend buffer. Once it is full, `send` waits for acknowledgements.
+    pub resend_buffer: Option<usize>,
+    // Lets the client notice a dead server, and reconnect, while it is idle.
+    pub keepalive: Option<Keepalive>,
+    pub format: F,
+}
+
+impl<F: Default> Default for Config<F> {
+    fn default() -> Self {
+        Config{
+            backoff: Backoff::default(),
+            max_attempts: None,
+            resend_buffer: None,
+            keepalive: None,
+            format: F::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum State {
+    Connecting { attempt: u32 },
+    Connected { peer: SocketAddr },
+    // Connecting failed, or the connection was lost (without an error if the server closed
+    // it). The next attempt starts after `retry_in`.
+    Disconnected { error: Option<Arc<crate::channel_implementations::Error>>, retry_in: Duration },
+    // The client gave up.
+    Closed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    // The client gave up reconnecting.
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Closed => f.write_str("gave up reconnecting"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Messages written to a connection but not acknowledged yet, oldest first, and the room
+// left for more.
+struct Unacked<I> {
+    messages: Mutex<VecDeque<I>>,
+    room: Semaphore,
+}
+
+// Sends `I` and receives `O`.
+pub struct Client<I, O> {
+    queue: mpsc::UnboundedSender<I>,
+    incoming: mpsc::UnboundedReceiver<O>,
+    state: watch::Receiver<State>,
+    unacked: Option<Arc<Unacked<I>>>,
+    // Dropped with the client, which stops the task reconnecting.
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl<I, O> Client<I, O> where
+    I: Serialize + DeserializeOwned + Clone + Send + 'static,
+    O: Serialize + DeserializeOwned + Send + 'static,
+{
+    // Spawns the task that connects to `address`, and keeps reconnecting, in the background.
+    // The address is resolved again on every attempt.
+    pub fn connect<A, F>(address: A, config: Config<F>) -> Self where
+        A: ToSocketAddrs + Clone + Send + Sync + 'static,
+        F: Format + Clone + Send + Sync + 'static,
+    {
+        let (queue, queued) = mpsc::unbounded_channel();
+        let (received, incoming) = mpsc::unbounded_channel();
+        let (state_sender, state) = watch::channel(State::Connecting{ attempt: 1 });
+        let (_shutdown, shutdown) = oneshot::channel();
+        let unacked = config.resend_buffer.map(|capacity| Arc::new(Unacked{
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+            room: Semaphore::new(capacity),
+        }));
+
+        let connection = Connection{ address, config, queued, received, state: state_sender, unacked: unacked.clone() };
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = connection.run() => {}
+                _ = shutdown => {}
+            }
+        });
+
+        Client{ queue, incoming, state, unacked, _shutdown }
+    }
+
+    // Queues `item` to be sent as soon as the client is connected. With a resend buffer,
+    // waits while the buffer is full, unless the client gives up meanwhile.
+    pub async fn send(&mut self, item: I) -> Result<(), Error> {
+        if let Some(unacked) = &self.unacked {
+            tokio::select! {
+                permit = unacked.room.acquire() => permit.forget(),
+                _ = closed(self.state.clone()) => return Err(Error::Closed),
+            }
+        }
+        self.queue.send(item).map_err(|_| Error::Closed)
+    }
+
+    // Receives from whichever connection is current. `None` once the client has given up.
+    pub async fn recv(&mut self) -> Option<O> {
+        self.incoming.recv().await
+    }
+
+    // Drops the oldest `messages` messages from the resend buffer.
+    pub fn acknowledge(&self, messages: usize) {
+        if let Some(unacked) = &self.unacked {
+            let mut buffered = unacked.messages.lock().unwrap();
+            let messages = messages.min(buffered.len());
+            buffered.drain(..messages);
+            unacked.room.add_permits(messages);
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state.borrow().clone()
+    }
+
+    // Yields the current state, then every change.
+    pub fn states(&self) -> watch::Receiver<State> {
+        self.state.clone()
+    }
+}
+
+// Completes once the client has given up, or its task has stopped.
+async fn closed(mut states: watch::Receiver<State>) {
+    loop {
+        if let State::Closed = *states.borrow() {
+            return;
+        }
+        if states.recv().await.is_none() {
+            return;
+        }
+    }
+}
+
+struct Connection<A, I, O, F> {
+    address: A,
+    config: Config<F>,
+    queued: mpsc::UnboundedReceiver<I>,
+    received: mpsc::UnboundedSender<O>,
+    state: watch::Sender<State>,
+    unacked: Option<Arc<Unacked<I>>>,
+}
+
+impl<A, I, O, F> Connection<A, I, O, F> where
+    A: ToSocketAddrs + Clone + Send + Sync + 'static,
+    I: Serialize + DeserializeOwned + Clone + Send + 'static,
+    O: Serialize + DeserializeOwned + Send + 'static,
+    F: Format + Clone + Send + Sync + 'static,
+{
+    async fn run(mut self) {
+        let mut attempt = 1;
+        loop {
+            let _ = self.state.broadcast(State::Connecting{ attempt });
+            let error = match self.connect().await {
+                Ok(channel) => {
+                    attempt = 0;
+                    match self.serve(channel).await {
+                        Ok(true) => break,
+                        Ok(false) => None,
+                        Err(e) => Some(Arc::new(e)),
+                    }
+                }
+                Err(e) => Some(Arc::new(e)),
+            };
+
+            if self.config.max_attempts.is_some_and(|max| attempt >= max) {
+                break;
+            }
+            // The first attempt after losing a connection is immediate.
+            let retry_in = if attempt == 0 { Duration::from_secs(0) } else { self.config.backoff.delay(attempt) };
+            let _ = self.state.broadcast(State::Disconnected{ error, retry_in });
+            tokio::time::delay_for(retry_in).await;
+            attempt += 1;
+        }
+        let _ = self.state.broadcast(State::Closed);
+    }
+
+    async fn connect(&self) -> Result<Channel<I, O, F>, crate::channel_implementations::Error> {
+        let channel = Channel::<I, O, Bincode>::connect(self.address.clone()).await?
+            .with_format(self.config.format.clone());
+        Ok(match self.config.keepalive {
+            Some(keepalive) => channel.with_keepalive(keepalive),
+            None => channel,
+        })
+    }
+
+    // Runs one connection until it fails. `Ok(true)` if the client was dropped, `Ok(false)`
+    // if the server closed the connection.
+    async fn serve(&mut self, channel: Channel<I, O, F>) -> Result<bool, crate::channel_implementations::Error> {
+        let peer = channel.peer_addr()?;
+        let (mut sender, mut receiver) = channel.into_split();
+        let _ = self.state.broadcast(State::Connected{ peer });
+
+        let resend: Vec<I> = match &self.unacked {
+            Some(unacked) => unacked.messages.lock().unwrap().iter().cloned().collect(),
+            None => Vec::new(),
+        };
+        for item in resend {
+            sender.send(item).await?;
+        }
+
+        // Reads in a task of its own, so the read-idle timeout isn't restarted whenever
+        // something is sent.
+        let received = self.received.clone();
+        let (reading, abort) = future::abortable(async move {
+            while let Some(item) = receiver.recv().await? {
+                let _ = received.send(item);
+            }
+            Ok(())
+        });
+        let mut reading = tokio::spawn(reading);
+
+        let result = loop {
+            tokio::select! {
+                item = self.queued.recv() => match item {
+                    Some(item) => {
+                        if let Some(unacked) = &self.unacked {
+                            unacked.messages.lock().unwrap().push_back(item.clone());
+                        }
+                        if let Err(e) = sender.send(item).await {
+                            break Err(e);
+                        }
+                    }
+                    None => break Ok(true),
+                },
+                read = &mut reading => match read {
+                    Ok(Ok(result)) => break result.map(|()| false),
+                    _ => break Ok(false),
+                },
+            }
+        };
+        abort.abort();
+        result
+    }
+}
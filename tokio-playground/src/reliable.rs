@@ -0,0 +1,241 @@
+use crate::channel_implementations::Transport;
+use crate::channel_implementations::asymmetric::{Channel, OwnedReceiver, OwnedSender};
+use crate::channel_implementations::format::{Bincode, Format};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use tokio::net::TcpStream;
+
+// Reliable delivery across connections.
+//
+// A session numbers the messages it sends and keeps each one until the peer acknowledges
+// it. Acknowledgements are cumulative: acknowledging a sequence number acknowledges every
+// message before it too. When the connection fails, both sides attach a new connection
+// to their sessions, tell each other how far they got, and the unacknowledged messages
+// the peer hasn't received are sent again. Messages the peer has seen already are dropped
+// on arrival, so each message is delivered exactly once, in order.
+//
+// Sessions outlive connections. Which session a new connection belongs to (e.g. one
+// session per client) is up to the application.
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Packet<T> {
+    // Sent by both sides first thing on a new connection: every message up to and including
+    // `delivered` has arrived.
+    Resume { delivered: u64 },
+    Message { seq: u64, body: T },
+    // Every message up to and including `seq` has arrived.
+    Ack { seq: u64 },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // No connection is attached.
+    Detached,
+    // `capacity` messages are unacknowledged and no connection is attached to acknowledge
+    // them.
+    Full,
+    // The connection failed and was detached. Unacknowledged messages are kept.
+    Channel(crate::channel_implementations::Error),
+    // The peer doesn't follow the protocol.
+    Protocol(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Detached => f.write_str("no connection attached"),
+            Error::Full => f.write_str("too many unacknowledged messages"),
+            Error::Channel(e) => write!(f, "connection failed: {}", e),
+            Error::Protocol(e) => write!(f, "protocol violation: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Channel(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<crate::channel_implementations::Error> for Error {
+    fn from(error: crate::channel_implementations::Error) -> Self {
+        Error::Channel(error)
+    }
+}
+
+struct Link<I, O, F, S: Transport> {
+    sender: OwnedSender<Packet<I>, Packet<O>, F, S>,
+    receiver: OwnedReceiver<Packet<I>, Packet<O>, F, S>,
+}
+
+// What reading a packet came to.
+enum Read<O> {
+    Message(O),
+    Closed,
+    // An acknowledgement or a duplicate.
+    Other,
+}
+
+// Sends `I` and receives `O` over whichever connection is attached.
+pub struct Session<I, O, F = Bincode, S: Transport = TcpStream> {
+    next_seq: u64,
+    // Sent, or waiting to be sent, but not acknowledged. Oldest first.
+    unacked: VecDeque<(u64, I)>,
+    capacity: usize,
+    delivered: u64,
+    // Arrived while `send` was waiting for acknowledgements.
+    received: VecDeque<O>,
+    link: Option<Link<I, O, F, S>>,
+}
+
+impl<I, O, F, S> Session<I, O, F, S> where
+    I: Serialize + DeserializeOwned + Clone,
+    O: Serialize + DeserializeOwned,
+    F: Format + Clone,
+    S: Transport,
+{
+    // Keeps at most `capacity` unacknowledged messages. Beyond that, `send` waits for
+    // acknowledgements, or fails with `Error::Full` while detached.
+    pub fn new(capacity: usize) -> Self {
+        Session{
+            next_seq: 1,
+            unacked: VecDeque::new(),
+            capacity: capacity.max(1),
+            delivered: 0,
+            received: VecDeque::new(),
+            link: None,
+        }
+    }
+
+    // Continues the session on `channel`, replacing the connection attached before if any.
+    // Both sides must attach at about the same time.
+    pub async fn attach(&mut self, channel: Channel<Packet<I>, Packet<O>, F, S>) -> Result<(), Error> {
+        self.link = None;
+        let (mut sender, mut receiver) = channel.into_split();
+        sender.send(Packet::Resume{ delivered: self.delivered }).await?;
+        match receiver.recv().await? {
+            Some(Packet::Resume{ delivered }) => self.acknowledged(delivered),
+            Some(_) => return Err(Error::Protocol("expected the peer to resume the session")),
+            None => return Err(Error::Channel(io::Error::from(io::ErrorKind::UnexpectedEof).into())),
+        }
+        for (seq, body) in &self.unacked {
+            sender.send(Packet::Message{ seq: *seq, body: body.clone() }).await?;
+        }
+        self.link = Some(Link{ sender, receiver });
+        Ok(())
+    }
+
+    pub fn detach(&mut self) {
+        self.link = None;
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.link.is_some()
+    }
+
+    // Messages not acknowledged by the peer yet.
+    pub fn unacknowledged(&self) -> usize {
+        self.unacked.len()
+    }
+
+    // Returns the message's sequence number once the message is kept, and an error only
+    // if it wasn't, so a failed send can simply be retried. While detached, or if sending
+    // fails (which detaches the connection), the message is sent once a connection is
+    // attached. Waits for acknowledgements while `capacity` messages are unacknowledged.
+    pub async fn send(&mut self, item: I) -> Result<u64, Error> {
+        self.wait_for_room().await?;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.unacked.push_back((seq, item.clone()));
+
+        if let Some(link) = &mut self.link {
+            if link.sender.send(Packet::Message{ seq, body: item }).await.is_err() {
+                self.link = None;
+            }
+        }
+        Ok(seq)
+    }
+
+    // `Ok(None)` when the peer closes the connection, which detaches it.
+    pub async fn recv(&mut self) -> Result<Option<O>, Error> {
+        match self.received.pop_front() {
+            Some(item) => Ok(Some(item)),
+            None => self.read().await,
+        }
+    }
+
+    // Reads until fewer than `capacity` messages are unacknowledged, keeping the messages
+    // that arrive meanwhile for `recv`.
+    async fn wait_for_room(&mut self) -> Result<(), Error> {
+        while self.unacked.len() >= self.capacity {
+            if self.link.is_none() {
+                return Err(Error::Full);
+            }
+            if let Read::Message(item) = self.read_packet().await? {
+                self.received.push_back(item);
+            }
+        }
+        Ok(())
+    }
+
+    // Reads until a message arrives that hasn't been delivered before, handling
+    // acknowledgements on the way.
+    async fn read(&mut self) -> Result<Option<O>, Error> {
+        loop {
+            match self.read_packet().await? {
+                Read::Message(item) => return Ok(Some(item)),
+                Read::Closed => return Ok(None),
+                Read::Other => {}
+            }
+        }
+    }
+
+    async fn read_packet(&mut self) -> Result<Read<O>, Error> {
+        let link = self.link.as_mut().ok_or(Error::Detached)?;
+        let packet = match link.receiver.recv().await {
+            Ok(Some(packet)) => packet,
+            Ok(None) => {
+                self.link = None;
+                return Ok(Read::Closed);
+            }
+            Err(e) => {
+                self.link = None;
+                return Err(e.into());
+            }
+        };
+        match packet {
+            Packet::Ack{ seq } => self.acknowledged(seq),
+            // A duplicate.
+            Packet::Message{ seq, .. } if seq <= self.delivered => {}
+            Packet::Message{ seq, body } if seq == self.delivered + 1 => {
+                self.delivered = seq;
+                // If this fails, the peer learns about the message when resuming.
+                if link.sender.send(Packet::Ack{ seq }).await.is_err() {
+                    self.link = None;
+                }
+                return Ok(Read::Message(body));
+            }
+            Packet::Message{ .. } => {
+                self.link = None;
+                return Err(Error::Protocol("messages arrived out of order"));
+            }
+            Packet::Resume{ .. } => {
+                self.link = None;
+                return Err(Error::Protocol("unexpected resume"));
+            }
+        }
+        Ok(Read::Other)
+    }
+
+    fn acknowledged(&mut self, seq: u64) {
+        while self.unacked.front().is_some_and(|(s, _)| *s <= seq) {
+            self.unacked.pop_front();
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::channel_implementations::Transport;
+use crate::channel_implementations::asymmetric::{Channel, OwnedReceiver, OwnedSender};
+use crate::channel_implementations::format::Format;
+use futures::future::{self, AbortHandle};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+// Request/response on top of an asymmetric channel.
+//
+// Every call carries an id that the server echoes in its response, so any number of calls
+// can be in flight at once and responses may arrive in any order. The server handles each
+// call in its own task. Dropping a call before it resolves (directly, or because its
+// timeout expired) tells the server to abort it.
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Request<T> {
+    Call { id: u64, body: T },
+    Cancel { id: u64 },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Response<T> {
+    pub id: u64,
+    pub body: T,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    // No response arrived within the call's timeout.
+    Timeout,
+    // The connection was closed, or failed, before the response arrived.
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Timeout => f.write_str("call timed out"),
+            Error::Closed => f.write_str("connection closed before the response arrived"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// `None` once the connection is gone, which fails the outstanding calls by dropping their
+// senders.
+type Pending<T> = Mutex<Option<HashMap<u64, oneshot::Sender<T>>>>;
+
+struct Shared<Req, Resp> {
+    next_id: AtomicU64,
+    pending: Pending<Resp>,
+    outgoing: mpsc::UnboundedSender<Request<Req>>,
+}
+
+impl<Req, Resp> Shared<Req, Resp> {
+    fn close(&self) {
+        self.pending.lock().unwrap().take();
+    }
+}
+
+// Cheap to clone; clones share the connection.
+pub struct Client<Req, Resp> {
+    shared: Arc<Shared<Req, Resp>>,
+}
+
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Client{ shared: self.shared.clone() }
+    }
+}
+
+impl<Req, Resp> Client<Req, Resp> where
+    Req: Serialize + DeserializeOwned + Send + 'static,
+    Resp: Serialize + DeserializeOwned + Send + 'static,
+{
+    // Spawns the tasks that write calls to, and read responses from, the channel.
+    pub fn new<F, S>(channel: Channel<Request<Req>, Response<Resp>, F, S>) -> Self where
+        F: Format + Clone + Send + Unpin + 'static,
+        S: Transport,
+    {
+        let (sender, receiver) = channel.into_split();
+        let (outgoing, queue) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared{
+            next_id: AtomicU64::new(0),
+            pending: Mutex::new(Some(HashMap::new())),
+            outgoing,
+        });
+
+        tokio::spawn(write_requests(sender, queue, Arc::downgrade(&shared)));
+        tokio::spawn(read_responses(receiver, Arc::downgrade(&shared)));
+
+        Client{ shared }
+    }
+
+    pub fn call(&self, request: Req) -> Call<Req, Resp> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, response) = oneshot::channel();
+        if let Some(pending) = self.shared.pending.lock().unwrap().as_mut() {
+            pending.insert(id, sender);
+        }
+        if self.shared.outgoing.send(Request::Call{ id, body: request }).is_err() {
+            self.shared.close();
+        }
+        Call{ id, response, shared: self.shared.clone(), done: false }
+    }
+
+    pub async fn call_timeout(&self, request: Req, timeout: Duration) -> Result<Resp, Error> {
+        self.call(request).timeout(timeout).await
+    }
+}
+
+async fn write_requests<Req, Resp, F, S>(
+    mut sender: OwnedSender<Request<Req>, Response<Resp>, F, S>,
+    mut queue: mpsc::UnboundedReceiver<Request<Req>>,
+    shared: std::sync::Weak<Shared<Req, Resp>>) where
+    Req: Serialize + DeserializeOwned,
+    Resp: Serialize + DeserializeOwned,
+    F: Format + Unpin,
+    S: Transport,
+{
+    while let Some(request) = queue.recv().await {
+        if sender.send(request).await.is_err() {
+            break;
+        }
+    }
+    if let Some(shared) = shared.upgrade() {
+        shared.close();
+    }
+}
+
+async fn read_responses<Req, Resp, F, S>(
+    mut receiver: OwnedReceiver<Request<Req>, Response<Resp>, F, S>,
+    shared: std::sync::Weak<Shared<Req, Resp>>) where
+    Req: Serialize + DeserializeOwned,
+    Resp: Serialize + DeserializeOwned,
+    F: Format + Unpin,
+    S: Transport,
+{
+    while let Ok(Some(response)) = receiver.recv().await {
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        let sender = shared.pending.lock().unwrap().as_mut().and_then(|p| p.remove(&response.id));
+        // The call may have been cancelled in the meantime.
+        if let Some(sender) = sender {
+            let _ = sender.send(response.body);
+        }
+    }
+    if let Some(shared) = shared.upgrade() {
+        shared.close();
+    }
+}
+
+// Resolves to the response of one call. Dropping it before then cancels the call.
+pub struct Call<Req, Resp> {
+    id: u64,
+    response: oneshot::Receiver<Resp>,
+    shared: Arc<Shared<Req, Resp>>,
+    done: bool,
+}
+
+impl<Req, Resp> Call<Req, Resp> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn cancel(self) {}
+
+    pub async fn timeout(mut self, timeout: Duration) -> Result<Resp, Error> {
+        tokio::time::timeout(timeout, &mut self).await.unwrap_or(Err(Error::Timeout))
+    }
+}
+
+impl<Req, Resp> Future for Call<Req, Resp> {
+    type Output = Result<Resp, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = futures::ready!(Pin::new(&mut self.response).poll(cx));
+        self.done = true;
+        Poll::Ready(result.map_err(|_| Error::Closed))
+    }
+}
+
+impl<Req, Resp> Drop for Call<Req, Resp> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let pending = self.shared.pending.lock().unwrap().as_mut().and_then(|p| p.remove(&self.id));
+        if pending.is_some() {
+            let _ = self.shared.outgoing.send(Request::Cancel{ id: self.id });
+        }
+    }
+}
+
+// Answers the calls arriving on `channel` with `handler`, each in its own task, until the
+// client disconnects.
+pub async fn serve<Req, Resp, F, S, H, Fut>(channel: Channel<Response<Resp>, Request<Req>, F, S>, handler: H)
+    -> Result<(), crate::channel_implementations::Error> where
+    Req: Serialize + DeserializeOwned + Send + 'static,
+    Resp: Serialize + DeserializeOwned + Send + 'static,
+    F: Format + Clone + Send + Unpin + 'static,
+    S: Transport + Send,
+    H: Fn(Req) -> Fut,
+    Fut: Future<Output = Resp> + Send + 'static,
+{
+    let (mut sender, mut receiver) = channel.into_split();
+    let (outgoing, mut queue) = mpsc::unbounded_channel::<Response<Resp>>();
+    let writer = tokio::spawn(async move {
+        while let Some(response) = queue.recv().await {
+            sender.send(response).await?;
+        }
+        Ok(())
+    });
+
+    let running: Arc<Mutex<HashMap<u64, AbortHandle>>> = Default::default();
+    let result = loop {
+        match receiver.recv().await {
+            Ok(Some(Request::Call{ id, body })) => {
+                let (call, abort) = future::abortable(handler(body));
+                running.lock().unwrap().insert(id, abort);
+                let (running, outgoing) = (running.clone(), outgoing.clone());
+                tokio::spawn(async move {
+                    let body = call.await;
+                    running.lock().unwrap().remove(&id);
+                    if let Ok(body) = body {
+                        let _ = outgoing.send(Response{ id, body });
+                    }
+                });
+            }
+            Ok(Some(Request::Cancel{ id })) => {
+                if let Some(abort) = running.lock().unwrap().remove(&id) {
+                    abort.abort();
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+
+    // Nobody is left to read the responses of calls that are still running.
+    for (_, abort) in running.lock().unwrap().drain() {
+        abort.abort();
+    }
+    drop(outgoing);
+    match writer.await {
+        Ok(written) => result.and(written),
+        Err(_) => result,
+    }
+}